@@ -1,28 +1,34 @@
 use std::hint::black_box;
 
 use criterion::{Criterion, criterion_group, criterion_main};
-use libdump1090_rs::{demod_2400::demodulate2400, icao_filter::icao_flush, utils};
-use num_complex::Complex;
+use libdump1090_rs::convert::SampleFormat;
+use libdump1090_rs::utils::IqReader;
+use libdump1090_rs::{Demodulator, utils};
 
-fn routine(data: [Complex<i16>; 0x20000]) {
+fn routine(data: &[u8]) {
     // make sure icao starts in a deterministic position
-    icao_flush();
-    let outbuf = utils::to_mag(&data);
-    let _ = black_box(demodulate2400(&outbuf).unwrap());
+    let mut demodulator = Demodulator::new();
+    let outbuf = utils::to_mag_bytes(SampleFormat::Cs16, data).unwrap();
+    let _ = black_box(demodulator.demodulate2400(&outbuf).unwrap());
+}
+
+fn read_test_data(filename: &str) -> Vec<u8> {
+    let mut reader = IqReader::open(filename, SampleFormat::Cs16).unwrap();
+    reader.next().unwrap().unwrap()
 }
 
 fn criterion_benchmark(c: &mut Criterion) {
     let filename = "test_iq/test_1641427457780.iq";
-    let data_01 = utils::read_test_data(filename);
+    let data_01 = read_test_data(filename);
 
     let filename = "test_iq/test_1641428165033.iq";
-    let data_02 = utils::read_test_data(filename);
+    let data_02 = read_test_data(filename);
 
     let filename = "test_iq/test_1641428106243.iq";
-    let data_03 = utils::read_test_data(filename);
-    c.bench_function("01", |b| b.iter(|| routine(data_01)));
-    c.bench_function("02", |b| b.iter(|| routine(data_02)));
-    c.bench_function("03", |b| b.iter(|| routine(data_03)));
+    let data_03 = read_test_data(filename);
+    c.bench_function("01", |b| b.iter(|| routine(&data_01)));
+    c.bench_function("02", |b| b.iter(|| routine(&data_02)));
+    c.bench_function("03", |b| b.iter(|| routine(&data_03)));
 }
 
 criterion_group!(benches, criterion_benchmark);
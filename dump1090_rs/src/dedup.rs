@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+/// Recently seen messages, for dropping the copies of a message heard by several receivers
+pub struct Dedup {
+    window: Duration,
+    /// time each message was last seen
+    seen: HashMap<Vec<u8>, SystemTime>,
+    /// time of the next removal of the messages older than `window`
+    next_expire: SystemTime,
+}
+
+impl Dedup {
+    pub fn new(window: Duration) -> Self {
+        Self { window, seen: HashMap::new(), next_expire: SystemTime::UNIX_EPOCH }
+    }
+
+    /// Whether `msg` at `time` is the first copy, as it wasn't passed within the window before
+    ///
+    /// Copies don't restart the window, so a message repeated more often than the window is
+    /// still passed once every window.
+    pub fn first(&mut self, msg: &[u8], time: SystemTime) -> bool {
+        let window = self.window;
+        if time >= self.next_expire {
+            self.seen.retain(|_, seen| time.duration_since(*seen).unwrap_or_default() < window);
+            self.next_expire = time + window;
+        }
+
+        if let Some(seen) = self.seen.get(msg)
+            && time.duration_since(*seen).unwrap_or_default() < window
+        {
+            return false;
+        }
+        self.seen.insert(msg.to_vec(), time);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let ms = Duration::from_millis;
+        let mut dedup = Dedup::new(ms(500));
+
+        assert!(dedup.first(b"a", start));
+        assert!(!dedup.first(b"a", start + ms(100)));
+        assert!(dedup.first(b"b", start + ms(100)));
+        // a copy from a receiver with a clock behind
+        assert!(!dedup.first(b"b", start));
+        assert!(!dedup.first(b"a", start + ms(400)));
+        assert!(dedup.first(b"a", start + ms(500)));
+
+        // expired messages are forgotten
+        assert!(dedup.first(b"c", start + ms(2_000)));
+        assert_eq!(dedup.seen.len(), 1);
+    }
+}
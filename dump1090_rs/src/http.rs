@@ -0,0 +1,338 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use libdump1090_rs::cpr::Receiver;
+use libdump1090_rs::mode_s::AirspeedType;
+use libdump1090_rs::track::{Aircraft, Tracker};
+use serde::Serialize;
+
+/// Interval between updates of the json documents, as in dump1090
+pub const JSON_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Read and write timeout of a connection, so a stalled client only holds its own thread
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Rendered json documents served by the http server
+#[derive(Debug, Default)]
+pub struct JsonFiles {
+    pub aircraft: String,
+    pub receiver: String,
+}
+
+/// Bind to `host`:`port` and serve `files` from a new thread, to at most `max_clients` clients at
+/// once
+pub fn spawn(
+    host: IpAddr,
+    port: u16,
+    max_clients: usize,
+    files: Arc<RwLock<JsonFiles>>,
+) -> std::io::Result<()> {
+    let listener = TcpListener::bind((host, port))?;
+    std::thread::spawn(move || serve(&listener, max_clients, &files));
+    Ok(())
+}
+
+/// Accept connections from `listener`, answering each on its own thread, and closing the
+/// connections beyond `max_clients` at once
+fn serve(listener: &TcpListener, max_clients: usize, files: &Arc<RwLock<JsonFiles>>) {
+    let clients = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming().flatten() {
+        if clients.load(Ordering::Relaxed) >= max_clients {
+            continue;
+        }
+        clients.fetch_add(1, Ordering::Relaxed);
+        let (files, thread_clients) = (Arc::clone(files), Arc::clone(&clients));
+        let handler = std::thread::Builder::new().name("http".to_string()).spawn(move || {
+            if let Err(e) = handle(stream, &files) {
+                println!("[!] http error: {e}");
+            }
+            thread_clients.fetch_sub(1, Ordering::Relaxed);
+        });
+        if let Err(e) = handler {
+            println!("[!] http error: {e}");
+            clients.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Answer one request, closing the connection afterwards
+fn handle(mut stream: TcpStream, files: &RwLock<JsonFiles>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    // ignore any query string, tar1090 adds one to avoid caching
+    let path = path.split('?').next().unwrap_or_default();
+
+    let body = {
+        let files = files.read().unwrap();
+        match path {
+            "/data/aircraft.json" | "/aircraft.json" => Some(files.aircraft.clone()),
+            "/data/receiver.json" | "/receiver.json" => Some(files.receiver.clone()),
+            _ => None,
+        }
+    };
+
+    let response = match (method, body) {
+        ("GET" | "HEAD", Some(body)) => {
+            let mut response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            if method == "GET" {
+                response.push_str(&body);
+            }
+            response
+        }
+        ("GET" | "HEAD", None) => {
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+        }
+        _ => "HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            .to_string(),
+    };
+    stream.write_all(response.as_bytes())
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum AltBaro {
+    Feet(i32),
+    Ground(&'static str),
+}
+
+/// One aircraft of aircraft.json, with the field names of dump1090-fa
+#[derive(Serialize)]
+struct AircraftJson {
+    hex: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flight: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alt_baro: Option<AltBaro>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alt_geom: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gs: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ias: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tas: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    track: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mag_heading: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    baro_rate: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    squawk: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    emergency: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    category: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lat: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lon: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seen_pos: Option<f64>,
+    messages: u64,
+    seen: f64,
+    rssi: f64,
+}
+
+impl AircraftJson {
+    fn new(a: &Aircraft, now: SystemTime) -> Self {
+        let alt_baro = if a.on_ground == Some(true) {
+            Some(AltBaro::Ground("ground"))
+        } else {
+            a.altitude.map(AltBaro::Feet)
+        };
+        let (ias, tas) = match a.airspeed {
+            Some((speed, AirspeedType::Indicated)) => (Some(speed), None),
+            Some((speed, AirspeedType::True)) => (None, Some(speed)),
+            None => (None, None),
+        };
+        let emergency = a.emergency.map(|e| match e {
+            0 => "none",
+            1 => "general",
+            2 => "lifeguard",
+            3 => "minfuel",
+            4 => "nordo",
+            5 => "unlawful",
+            6 => "downed",
+            _ => "reserved",
+        });
+
+        Self {
+            hex: format!("{:06x}", a.icao),
+            // dump1090 pads the callsign to 8 characters
+            flight: a.callsign.as_ref().map(|c| format!("{c:<8}")),
+            alt_baro,
+            alt_geom: a.altitude_gnss,
+            gs: a.ground_speed.map(round1),
+            ias,
+            tas,
+            track: a.track.map(round1),
+            mag_heading: a.heading.map(round1),
+            baro_rate: a.vertical_rate,
+            squawk: a.squawk.map(|s| s.to_string()),
+            emergency,
+            category: a.category.map(|c| format!("{c:02X}")),
+            version: a.adsb_version,
+            lat: a.position.map(|p| round6(p.lat)),
+            lon: a.position.map(|p| round6(p.lon)),
+            seen_pos: a.position_time.map(|t| round1(seconds_since(t, now))),
+            messages: a.messages,
+            seen: round1(a.seen(now).as_secs_f64()),
+            rssi: round1(a.rssi()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct AircraftFile {
+    now: f64,
+    messages: u64,
+    aircraft: Vec<AircraftJson>,
+}
+
+#[derive(Serialize)]
+struct ReceiverFile {
+    version: &'static str,
+    /// Milliseconds between updates of aircraft.json
+    refresh: u128,
+    history: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lat: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lon: Option<f64>,
+}
+
+/// Render aircraft.json from the state of `tracker`, with a total count of `messages`
+pub fn aircraft_json(tracker: &Tracker, messages: u64, now: SystemTime) -> String {
+    let file = AircraftFile {
+        now: round1(seconds_since(UNIX_EPOCH, now)),
+        messages,
+        aircraft: tracker.iter().map(|a| AircraftJson::new(a, now)).collect(),
+    };
+    serde_json::to_string(&file).unwrap()
+}
+
+/// Render receiver.json, with the receiver location if known
+pub fn receiver_json(receiver: &Receiver) -> String {
+    let file = ReceiverFile {
+        version: env!("CARGO_PKG_VERSION"),
+        refresh: JSON_INTERVAL.as_millis(),
+        history: 0,
+        lat: receiver.location.map(|l| l.lat),
+        lon: receiver.location.map(|l| l.lon),
+    };
+    serde_json::to_string(&file).unwrap()
+}
+
+fn seconds_since(t: SystemTime, now: SystemTime) -> f64 {
+    now.duration_since(t).unwrap_or_default().as_secs_f64()
+}
+
+fn round1(x: f64) -> f64 {
+    (x * 10.0).round() / 10.0
+}
+
+fn round6(x: f64) -> f64 {
+    (x * 1_000_000.0).round() / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libdump1090_rs::cpr::Position;
+    use libdump1090_rs::mode_s;
+    use std::io::Read;
+
+    #[test]
+    fn aircraft() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_641_428_165);
+        let mut tracker = Tracker::new();
+        let msgs = [
+            "8d4840d6202cc371c32ce0576098",
+            "8d40621d58c386435cc412692ad6",
+            "8d40621d58c382d690c8ac2863a7",
+        ];
+        for (i, msg) in msgs.iter().enumerate() {
+            let decoded = mode_s::decode(&hex::decode(msg).unwrap()).unwrap();
+            tracker.update_decoded(&decoded, 0.1, start + Duration::from_secs(i as u64));
+        }
+
+        let json = aircraft_json(&tracker, 3, start + Duration::from_secs(3));
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json["now"], 1_641_428_168.0);
+        assert_eq!(json["messages"], 3);
+
+        let aircraft = json["aircraft"].as_array().unwrap();
+        let klm = aircraft.iter().find(|a| a["hex"] == "4840d6").unwrap();
+        assert_eq!(klm["flight"], "KLM1023 ");
+        assert_eq!(klm["category"], "A0");
+        assert_eq!(klm["seen"], 3.0);
+        assert_eq!(klm["rssi"], -10.0);
+        assert!(klm.get("lat").is_none());
+
+        let a = aircraft.iter().find(|a| a["hex"] == "40621d").unwrap();
+        assert_eq!(a["alt_baro"], 38000);
+        assert_eq!(a["lat"], 52.257202);
+        assert_eq!(a["lon"], 3.919373);
+        assert_eq!(a["seen_pos"], 1.0);
+        assert_eq!(a["messages"], 2);
+    }
+
+    #[test]
+    fn stalled_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let files = Arc::new(RwLock::new(JsonFiles {
+            aircraft: "{}".to_string(),
+            receiver: String::new(),
+        }));
+        std::thread::spawn(move || serve(&listener, 2, &files));
+
+        // a client that never sends its request doesn't hold up the others
+        let stalled = TcpStream::connect(addr).unwrap();
+        let get = |client: &mut TcpStream| {
+            client.set_read_timeout(Some(TIMEOUT / 2)).unwrap();
+            client.write_all(b"GET /data/aircraft.json HTTP/1.1\r\n\r\n").unwrap();
+            let mut response = String::new();
+            client.read_to_string(&mut response).map(|_| response)
+        };
+        let response = get(&mut TcpStream::connect(addr).unwrap()).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\n{}"));
+
+        // connections beyond the maximum are closed without a response, once the answered one is
+        // finished and another one is waiting
+        std::thread::sleep(Duration::from_millis(100));
+        let _second = TcpStream::connect(addr).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        let refused = get(&mut TcpStream::connect(addr).unwrap());
+        assert!(matches!(refused.as_deref(), Ok("") | Err(_)), "{refused:?}");
+        drop(stalled);
+    }
+
+    #[test]
+    fn receiver() {
+        let json = receiver_json(&Receiver::new(Some(Position::new(52.3, 4.7)), 0.0));
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json["refresh"], 1000);
+        assert_eq!(json["lat"], 52.3);
+        assert_eq!(json["lon"], 4.7);
+
+        let json = receiver_json(&Receiver::default());
+        assert!(!json.contains("lat"));
+    }
+}
@@ -0,0 +1,116 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+use libdump1090_rs::convert::SampleFormat;
+use libdump1090_rs::utils::IqReader;
+
+use crate::sigmf;
+
+/// IQ recording read in blocks, replacing the sdr as input
+pub struct FileInput {
+    path: PathBuf,
+    reader: IqReader<BufReader<File>>,
+    /// start again from the beginning at the end of the file
+    looping: bool,
+    /// pace the reads to the sample rate instead of reading as fast as possible
+    realtime: bool,
+    sample_rate: f64,
+    start: Instant,
+    samples_read: u64,
+    /// wall clock time of the first sample, from the sigmf meta file
+    start_time: Option<SystemTime>,
+    /// count of messages annotated in the sigmf meta file
+    annotations: usize,
+}
+
+impl FileInput {
+    pub fn open(
+        path: impl Into<PathBuf>,
+        format: SampleFormat,
+        looping: bool,
+        realtime: bool,
+        sample_rate: f64,
+    ) -> std::io::Result<Self> {
+        let mut path = path.into();
+        let mut format = format;
+        let mut sample_rate = sample_rate;
+        let mut start_time = None;
+        let mut annotations = 0;
+
+        // sigmf recordings describe their own format
+        if sigmf::is_sigmf(&path) {
+            let (data_path, meta_path) = sigmf::paths(&path);
+            let meta = sigmf::read_meta(&meta_path)?;
+            if let Some(rate) = meta.global.sample_rate {
+                sample_rate = rate;
+            }
+            format = meta.format()?;
+            start_time = meta.start_time();
+            annotations = meta.annotations.len();
+            path = data_path;
+        }
+
+        let reader = IqReader::open(&path, format)?;
+        Ok(Self {
+            path,
+            reader,
+            looping,
+            realtime,
+            sample_rate,
+            start: Instant::now(),
+            samples_read: 0,
+            start_time,
+            annotations,
+        })
+    }
+
+    pub fn format(&self) -> SampleFormat {
+        self.reader.format()
+    }
+
+    /// Sample rate in Hz, from the sigmf meta file if the recording has one
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    /// Wall clock time of the first sample, if the recording has one
+    pub fn start_time(&self) -> Option<SystemTime> {
+        self.start_time
+    }
+
+    /// Count of messages annotated in the recording
+    pub fn annotations(&self) -> usize {
+        self.annotations
+    }
+
+    /// Count of samples read so far
+    pub fn samples_read(&self) -> u64 {
+        self.samples_read
+    }
+
+    /// Read the next block of raw samples, `None` at the end of the file
+    pub fn read(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        let mut chunk = self.reader.next().transpose()?;
+        if chunk.is_none() && self.looping {
+            // reopen once per read, so an empty file doesn't spin forever
+            self.reader = IqReader::open(&self.path, self.reader.format())?;
+            chunk = self.reader.next().transpose()?;
+        }
+        let Some(chunk) = chunk else {
+            return Ok(None);
+        };
+
+        let len = (chunk.len() / self.reader.format().bytes_per_sample()) as u64;
+        if self.realtime {
+            let end = Duration::from_secs_f64((self.samples_read + len) as f64 / self.sample_rate);
+            if let Some(wait) = end.checked_sub(self.start.elapsed()) {
+                std::thread::sleep(wait);
+            }
+        }
+        self.samples_read += len;
+
+        Ok(Some(chunk))
+    }
+}
@@ -1,16 +1,39 @@
+mod dedup;
+mod http;
+mod ifile;
+mod net;
 mod sdrconfig;
+mod sigmf;
 
-use std::io::Write;
-use std::net::{IpAddr, TcpListener};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::mpsc::Receiver as QueueReceiver;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
 
 use clap::Parser;
-use libdump1090_rs::demod_2400::demodulate2400;
-use libdump1090_rs::utils;
+use dedup::Dedup;
+use ifile::FileInput;
+use libdump1090_rs::convert::SampleFormat;
+use libdump1090_rs::cpr::{Position, Receiver};
+use libdump1090_rs::demod_2400::ModeSMessage;
+use libdump1090_rs::mode_ac::ModeACMessage;
+use libdump1090_rs::net_io::Reply;
+use libdump1090_rs::track::{Tracker, TrackerConfig};
+use libdump1090_rs::{Demodulator, DemodulatorConfig, Error, MagnitudeBuffer, SampleRate, net_io};
+use net::{
+    Connector, InputBatch, InputFormat, InputServer, OutputFormat, RemoteSpec, Server, UdpSink,
+};
 use num_complex::Complex;
-use sdrconfig::{DEFAULT_CONFIG, SdrConfig};
+use sdrconfig::{DEFAULT_CONFIG, Sdr, SdrConfig};
 use soapysdr::Direction;
 
 const DIRECTION: Direction = Direction::Rx;
+const FREQUENCY: f64 = 1_090_000_000.0;
+/// Batches of frames from network input queued before the reader threads wait
+const INPUT_QUEUE_LEN: usize = 1024;
+/// Longest wait for network input with `--net-only`, before the outputs are serviced anyway
+const NET_ONLY_WAIT: Duration = Duration::from_millis(100);
 
 const CUSTOM_CONFIG_HELP: &str =
     "Filepath for config.toml file overriding or adding sdr config values for soapysdr";
@@ -46,6 +69,60 @@ struct Options {
     #[clap(long, default_value = "30002")]
     port: u16,
 
+    /// port to bind with for Beast binary output client connections
+    #[clap(long, default_value = "30005")]
+    net_bo_port: u16,
+
+    /// port to bind with for SBS/BaseStation output client connections
+    #[clap(long, default_value = "30003")]
+    net_sbs_port: u16,
+
+    /// port to bind with for http connections serving `data/aircraft.json` and `data/receiver.json`
+    #[clap(long)]
+    net_http_port: Option<u16>,
+
+    /// maximum number of clients connected to each of `--port`, `--net-bo-port`, `--net-sbs-port`,
+    /// `--net-http-port`, `--net-bi-port` and `--net-ri-port`
+    #[clap(long, default_value = "64")]
+    net_max_clients: usize,
+
+    /// push messages to `host:port:format`, with format `avr`, `beast` or `sbs`, reconnecting
+    /// whenever the connection drops
+    #[clap(long)]
+    net_connector: Vec<RemoteSpec>,
+
+    /// send each message as a datagram to `host:port:format`, a unicast or multicast address, with
+    /// format `avr`, `beast` or `sbs`
+    #[clap(long)]
+    net_udp: Vec<RemoteSpec>,
+
+    /// time to live, or IPv6 hop limit, of `--net-udp` datagrams, the number of routers they may
+    /// cross, otherwise 1 for multicast and the system default for unicast
+    #[clap(long)]
+    net_udp_ttl: Option<u32>,
+
+    /// local address to send `--net-udp` datagrams from, and the interface of IPv4 multicast
+    /// datagrams
+    #[clap(long)]
+    net_udp_interface: Option<IpAddr>,
+
+    /// port to bind with for Beast binary input from other receivers, merged into the outputs
+    #[clap(long)]
+    net_bi_port: Option<u16>,
+
+    /// port to bind with for AVR input from other receivers, merged into the outputs
+    #[clap(long)]
+    net_ri_port: Option<u16>,
+
+    /// milliseconds in which copies of a message, such as from several receivers, are dropped
+    /// when reading `--net-bi-port` or `--net-ri-port` (0 to keep copies)
+    #[clap(long, default_value = "500")]
+    net_dedup_ms: u64,
+
+    /// read only from `--net-bi-port` and `--net-ri-port`, without an sdr
+    #[clap(long, conflicts_with_all = ["ifile", "record"])]
+    net_only: bool,
+
     /// soapysdr driver name (sdr device) from default `config.toml` or `--custom-config`
     ///
     /// This is used both for instructing soapysdr how to find the sdr and what sdr is being used,
@@ -64,6 +141,51 @@ struct Options {
     /// don't display hex output of messages
     #[clap(long)]
     quiet: bool,
+
+    /// seconds an ICAO address is remembered for validating messages with the address in the parity
+    #[clap(long, default_value = "60")]
+    icao_filter_ttl: u64,
+
+    /// number of bit errors to fix with CRC error correction of DF11/DF17/DF18 messages (0 to 2)
+    #[clap(long, default_value = "0", value_parser = clap::value_parser!(u8).range(0..=2))]
+    fix_errors: u8,
+
+    /// receiver latitude in degrees, used for position decoding and `receiver.json`
+    #[clap(long, requires = "lon", allow_negative_numbers = true)]
+    lat: Option<f64>,
+
+    /// receiver longitude in degrees, used for position decoding and `receiver.json`
+    #[clap(long, requires = "lat", allow_negative_numbers = true)]
+    lon: Option<f64>,
+
+    /// maximum range of the receiver in nautical miles, positions further away are discarded (0 for no limit)
+    #[clap(long, default_value = "300")]
+    max_range: f64,
+
+    /// read samples from an IQ recording instead of the sdr
+    #[clap(long)]
+    ifile: Option<String>,
+
+    /// sample format of `--ifile`: cu8, cs8, cs16 or cf32
+    #[clap(long, default_value = "cs16")]
+    iformat: SampleFormat,
+
+    /// restart `--ifile` from the beginning when the end is reached
+    #[clap(long, requires = "ifile")]
+    ifile_loop: bool,
+
+    /// read `--ifile` at the sample rate instead of as fast as possible
+    #[clap(long, requires = "ifile")]
+    throttle: bool,
+
+    /// decode Mode A/C replies, sent to `--port` and `--net-bo-port` clients
+    #[clap(long)]
+    modeac: bool,
+
+    /// record the samples from the sdr to `<RECORD>.sigmf-data` and `<RECORD>.sigmf-meta`,
+    /// annotated with the decoded messages
+    #[clap(long, conflicts_with = "ifile")]
+    record: Option<PathBuf>,
 }
 
 // main will exit as 0 for success, 1 on error
@@ -75,9 +197,9 @@ fn main() {
     let options = Options::parse();
 
     // parse config from custom filepath
-    if let Some(config_filepath) = options.custom_config {
+    if let Some(config_filepath) = &options.custom_config {
         let custom_config: SdrConfig =
-            toml::from_str(&std::fs::read_to_string(&config_filepath).unwrap()).unwrap();
+            toml::from_str(&std::fs::read_to_string(config_filepath).unwrap()).unwrap();
         println!("[-] read in custom config: {config_filepath}");
         // push new configs to the front, so that the `find` method finds these first
         for sdr in custom_config.sdrs {
@@ -85,11 +207,417 @@ fn main() {
         }
     }
 
+    // sample rate of the selected --driver, or the default
+    let sdr = config.sdrs.iter().find(|a| a.driver == options.driver);
+    let config_rate = sdr.map_or_else(Sdr::default_sample_rate, |sdr| sdr.sample_rate);
+
+    let input = options.ifile.as_ref().map(|ifile| {
+        let input = FileInput::open(
+            ifile,
+            options.iformat,
+            options.ifile_loop,
+            options.throttle,
+            config_rate,
+        );
+        match input {
+            Ok(input) => input,
+            Err(e) => {
+                println!("[!] exiting: could not open {ifile}: {e}");
+                std::process::exit(1);
+            }
+        }
+    });
+    let format = input.as_ref().map_or(options.iformat, FileInput::format);
+    let rate = input.as_ref().map_or(config_rate, FileInput::sample_rate);
+    let Some(sample_rate) = SampleRate::from_hz(rate as u32).filter(|s| f64::from(s.hz()) == rate)
+    else {
+        println!(
+            "[!] exiting: unsupported sample rate {rate}, expected 2000000, 2400000, 6000000, 8000000 or 12000000"
+        );
+        std::process::exit(1);
+    };
+
+    if options.net_only && options.net_bi_port.is_none() && options.net_ri_port.is_none() {
+        println!("[!] exiting: --net-only without --net-bi-port or --net-ri-port");
+        std::process::exit(1);
+    }
+
+    let mut pipeline = Pipeline::new(&options, format, sample_rate);
+
+    if options.net_only {
+        run_net(&mut pipeline);
+    } else if let (Some(ifile), Some(input)) = (&options.ifile, input) {
+        run_file(ifile, input, &mut pipeline);
+    } else {
+        run_sdr(&options, &config, &mut pipeline);
+    }
+}
+
+/// Demodulator, and the outputs fed from its messages
+struct Pipeline {
+    demodulator: Demodulator,
+    tracker: Tracker,
+    /// documents of the http server, with `--net-http-port`
+    json_files: Option<Arc<RwLock<http::JsonFiles>>>,
+    messages: u64,
+    next_json_update: SystemTime,
+    avr_server: Server,
+    beast_server: Server,
+    sbs_server: Server,
+    connectors: Vec<Connector>,
+    udp_sinks: Vec<UdpSink>,
+    inputs: Vec<InputServer>,
+    /// frames read by all `inputs`
+    input_queue: QueueReceiver<InputBatch>,
+    /// copies of messages are dropped when there are `inputs`
+    dedup: Option<Dedup>,
+    quiet: bool,
+    mode_ac: bool,
+    sample_rate: SampleRate,
+    recorder: Option<sigmf::Recorder>,
+}
+
+impl Pipeline {
+    fn new(options: &Options, format: SampleFormat, sample_rate: SampleRate) -> Self {
+        // bind to listener ports
+        let max_clients = options.net_max_clients;
+        let avr_server = bind_or_exit(
+            options.port,
+            Server::bind("avr", options.host, options.port, max_clients),
+        );
+        let beast_server = bind_or_exit(
+            options.net_bo_port,
+            Server::bind("beast", options.host, options.net_bo_port, max_clients),
+        );
+        let sbs_server = bind_or_exit(
+            options.net_sbs_port,
+            Server::bind("sbs", options.host, options.net_sbs_port, max_clients),
+        );
+        let connectors = options
+            .net_connector
+            .iter()
+            .map(|spec| {
+                Connector::spawn(spec.clone()).unwrap_or_else(|e| {
+                    println!("[!] exiting: could not start --net-connector {spec}: {e}");
+                    std::process::exit(1);
+                })
+            })
+            .collect();
+        let udp_sinks = options
+            .net_udp
+            .iter()
+            .map(|spec| {
+                let sink =
+                    UdpSink::bind(spec.clone(), options.net_udp_interface, options.net_udp_ttl);
+                sink.unwrap_or_else(|e| {
+                    println!("[!] exiting: could not open --net-udp {spec}: {e}");
+                    std::process::exit(1);
+                })
+            })
+            .collect();
+        let (queue, input_queue) = std::sync::mpsc::sync_channel(INPUT_QUEUE_LEN);
+        let mut inputs = vec![];
+        for (format, port) in
+            [(InputFormat::Beast, options.net_bi_port), (InputFormat::Avr, options.net_ri_port)]
+        {
+            if let Some(port) = port {
+                let input =
+                    InputServer::bind(format, options.host, port, max_clients, queue.clone());
+                inputs.push(bind_or_exit(port, input));
+            }
+        }
+        let dedup = (!inputs.is_empty() && options.net_dedup_ms > 0)
+            .then(|| Dedup::new(Duration::from_millis(options.net_dedup_ms)));
+
+        let demodulator = Demodulator::with_config(DemodulatorConfig {
+            icao_filter_ttl: Duration::from_secs(options.icao_filter_ttl),
+            fix_errors: usize::from(options.fix_errors),
+            format,
+            sample_rate,
+        });
+
+        // aircraft state, served as json over http
+        let location = options.lat.zip(options.lon).map(|(lat, lon)| Position::new(lat, lon));
+        let receiver = Receiver::new(location, options.max_range * 1852.0);
+        let tracker = Tracker::with_config(TrackerConfig { receiver, ..TrackerConfig::default() });
+        let json_files = options.net_http_port.map(|port| {
+            let json_files = Arc::new(RwLock::new(http::JsonFiles {
+                aircraft: http::aircraft_json(&tracker, 0, SystemTime::now()),
+                receiver: http::receiver_json(&receiver),
+            }));
+            let server = http::spawn(options.host, port, max_clients, Arc::clone(&json_files));
+            bind_or_exit(port, server);
+            json_files
+        });
+
+        Self {
+            demodulator,
+            tracker,
+            json_files,
+            messages: 0,
+            next_json_update: SystemTime::now() + http::JSON_INTERVAL,
+            avr_server,
+            beast_server,
+            sbs_server,
+            connectors,
+            udp_sinks,
+            inputs,
+            input_queue,
+            dedup,
+            quiet: options.quiet,
+            mode_ac: options.modeac,
+            sample_rate,
+            recorder: None,
+        }
+    }
+
+    /// Demodulate a block of samples, the first of which was received at `sys_timestamp`, and
+    /// send the messages to all outputs
+    fn process(&mut self, buf: &[Complex<i16>], sys_timestamp: SystemTime) -> Result<(), Error> {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.write_samples(buf)?;
+        }
+        let mag = self.demodulator.convert(buf, sys_timestamp)?;
+        let resulting_data = self.demodulator.demodulate_mag(&mag)?;
+        if let Some(recorder) = &mut self.recorder {
+            for msg in &resulting_data {
+                recorder.annotate(msg);
+            }
+        }
+        let mode_ac = self.demodulate_mode_ac(&mag);
+        self.output(resulting_data, mode_ac, sys_timestamp);
+        Ok(())
+    }
+
+    /// [`Self::process`] for raw samples in the format of `--iformat`
+    fn process_bytes(&mut self, buf: &[u8], sys_timestamp: SystemTime) -> Result<(), Error> {
+        let mag = self.demodulator.convert_bytes(buf, sys_timestamp)?;
+        let resulting_data = self.demodulator.demodulate_mag(&mag)?;
+        let mode_ac = self.demodulate_mode_ac(&mag);
+        self.output(resulting_data, mode_ac, sys_timestamp);
+        Ok(())
+    }
+
+    /// Check the frames from network input, waiting up to `wait` for the first batch
+    fn read_inputs(&mut self, wait: Duration) -> (Vec<ModeSMessage>, Vec<ModeACMessage>) {
+        let mut mode_s = vec![];
+        let mut mode_ac = vec![];
+        if self.inputs.is_empty() {
+            return (mode_s, mode_ac);
+        }
+        let first = self.input_queue.recv_timeout(wait).ok();
+        for batch in first.into_iter().chain(self.input_queue.try_iter()) {
+            for frame in &batch.frames {
+                match self.demodulator.decode_frame(frame, batch.sys_timestamp) {
+                    Some(Reply::ModeS(msg)) => mode_s.push(msg),
+                    Some(Reply::ModeAC(msg)) if self.mode_ac => mode_ac.push(msg),
+                    Some(Reply::ModeAC(_)) | None => (),
+                }
+            }
+        }
+        (mode_s, mode_ac)
+    }
+
+    fn demodulate_mode_ac(&self, mag: &MagnitudeBuffer) -> Vec<ModeACMessage> {
+        if self.mode_ac { self.demodulator.demodulate_mode_ac(mag) } else { vec![] }
+    }
+
+    /// Merge `resulting_data` and `mode_ac` with network input, and send them to all outputs
+    fn output(
+        &mut self,
+        mut resulting_data: Vec<ModeSMessage>,
+        mut mode_ac: Vec<ModeACMessage>,
+        sys_timestamp: SystemTime,
+    ) {
+        // add more clients
+        self.avr_server.accept();
+        self.beast_server.accept();
+        self.sbs_server.accept();
+        for input in &mut self.inputs {
+            input.accept();
+        }
+
+        let (input_mode_s, input_mode_ac) = self.read_inputs(Duration::ZERO);
+        resulting_data.extend(input_mode_s);
+        mode_ac.extend(input_mode_ac);
+        if let Some(dedup) = &mut self.dedup {
+            resulting_data.retain(|msg| dedup.first(msg.buffer(), msg.sys_timestamp()));
+            mode_ac.retain(|msg| dedup.first(&msg.buffer(), msg.sys_timestamp()));
+        }
+
+        // the position each message decoded to, for SBS output
+        let positions: Vec<Option<Position>> = resulting_data
+            .iter()
+            .map(|msg| {
+                let aircraft = self.tracker.update(msg);
+                aircraft.position.filter(|_| aircraft.position_time == Some(msg.sys_timestamp()))
+            })
+            .collect();
+        self.messages += resulting_data.len() as u64;
+        let now = SystemTime::now();
+        if now >= self.next_json_update {
+            self.tracker.expire(sys_timestamp);
+            if let Some(json_files) = &self.json_files {
+                json_files.write().unwrap().aircraft =
+                    http::aircraft_json(&self.tracker, self.messages, sys_timestamp);
+            }
+            self.next_json_update = now + http::JSON_INTERVAL;
+            if let Some(recorder) = &mut self.recorder
+                && let Err(e) = recorder.flush()
+            {
+                println!("[!] could not write recording, stopping it: {e}");
+                self.recorder = None;
+            }
+        }
+
+        // send new data to connected clients
+        if !resulting_data.is_empty() {
+            self.broadcast(&resulting_data, &positions);
+        }
+        if !mode_ac.is_empty() {
+            self.broadcast_mode_ac(&mode_ac);
+        }
+    }
+
+    /// Mode A/C replies have no SBS representation, and no address for the tracker
+    fn broadcast_mode_ac(&mut self, mode_ac: &[ModeACMessage]) {
+        let avr_data: Vec<Vec<u8>> = mode_ac
+            .iter()
+            .map(|a| {
+                let a = format!("*{};\n", hex::encode(a.buffer()));
+                if !self.quiet {
+                    println!("{}", &a[..a.len() - 1]);
+                }
+                a.into_bytes()
+            })
+            .collect();
+        self.send(OutputFormat::Avr, &avr_data);
+
+        let beast_data: Vec<Vec<u8>> = mode_ac
+            .iter()
+            .map(|a| {
+                let mut out = vec![];
+                net_io::beast_encode_mode_ac(a, &mut out);
+                out
+            })
+            .collect();
+        self.send(OutputFormat::Beast, &beast_data);
+    }
+
+    fn broadcast(&mut self, resulting_data: &[ModeSMessage], positions: &[Option<Position>]) {
+        let avr_data: Vec<Vec<u8>> = resulting_data
+            .iter()
+            .map(|a| {
+                let msg = a.buffer();
+                let h = hex::encode(msg);
+                let a = format!("*{h};\n");
+                if !self.quiet {
+                    println!("{}", &a[..a.len() - 1]);
+                }
+                a.into_bytes()
+            })
+            .collect();
+        self.send(OutputFormat::Avr, &avr_data);
+
+        let beast_data: Vec<Vec<u8>> = resulting_data
+            .iter()
+            .map(|a| {
+                let mut out = vec![];
+                net_io::beast_encode(a, &mut out);
+                out
+            })
+            .collect();
+        self.send(OutputFormat::Beast, &beast_data);
+
+        let sbs_data: Vec<Vec<u8>> = resulting_data
+            .iter()
+            .zip(positions)
+            .map(|(a, position)| {
+                let mut out = vec![];
+                net_io::sbs_encode(a, *position, &mut out);
+                out
+            })
+            .filter(|a| !a.is_empty())
+            .collect();
+        self.send(OutputFormat::Sbs, &sbs_data);
+    }
+
+    /// Send messages encoded as `format` to its server, connectors and UDP sinks
+    fn send(&mut self, format: OutputFormat, data: &[Vec<u8>]) {
+        let server = match format {
+            OutputFormat::Avr => &mut self.avr_server,
+            OutputFormat::Beast => &mut self.beast_server,
+            OutputFormat::Sbs => &mut self.sbs_server,
+        };
+        server.broadcast(data);
+        for connector in self.connectors.iter().filter(|c| c.format() == format) {
+            connector.broadcast(data);
+        }
+        for sink in self.udp_sinks.iter_mut().filter(|s| s.format() == format) {
+            sink.broadcast(data);
+        }
+    }
+}
+
+/// Unwrap the listener bound to `port`, exiting when the port can't be bound
+fn bind_or_exit<T>(port: u16, listener: std::io::Result<T>) -> T {
+    listener.unwrap_or_else(|e| {
+        println!("[!] exiting: could not bind to port {port}: {e}");
+        std::process::exit(1);
+    })
+}
+
+/// Read from network input only, with `--net-only`
+fn run_net(pipeline: &mut Pipeline) {
+    println!("[-] reading network input only");
+    loop {
+        let (mode_s, mode_ac) = pipeline.read_inputs(NET_ONLY_WAIT);
+        pipeline.output(mode_s, mode_ac, SystemTime::now());
+    }
+}
+
+/// Read samples from `--ifile` until the end of the file
+fn run_file(ifile: &str, mut input: FileInput, pipeline: &mut Pipeline) {
+    println!("[-] reading {ifile} as {} at {}Hz", input.format(), pipeline.sample_rate.hz());
+    let sample_rate = f64::from(pipeline.sample_rate.hz());
+
+    // timestamps of the recording are counted from its start time, or the start of reading
+    let start = input.start_time().unwrap_or_else(SystemTime::now);
+    loop {
+        let samples_read = input.samples_read();
+        match input.read() {
+            Ok(None) => {
+                println!(
+                    "[-] end of {ifile}: {} messages, {} annotated in recording",
+                    pipeline.messages,
+                    input.annotations()
+                );
+                return;
+            }
+            Ok(Some(buf)) => {
+                let sys_timestamp =
+                    start + Duration::from_secs_f64(samples_read as f64 / sample_rate);
+                if let Err(e) = pipeline.process_bytes(&buf, sys_timestamp) {
+                    println!("[!] exiting: could not demodulate {ifile}: {e}");
+                    std::process::exit(1);
+                }
+            }
+            Err(e) => {
+                println!("[!] exiting: could not read {ifile}: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Read samples from the soapysdr device selected with `--driver`
+fn run_sdr(options: &Options, config: &SdrConfig, pipeline: &mut Pipeline) {
     // setup soapysdr driver
+    let sample_rate = f64::from(pipeline.sample_rate.hz());
     let mut driver = String::new();
     driver.push_str(&format!("driver={}", options.driver));
 
-    for e in options.driver_extra {
+    for e in &options.driver_extra {
         driver.push_str(&format!(",{e}"));
     }
 
@@ -130,10 +658,10 @@ fn main() {
         }
 
         // now we set defaults
-        d.set_frequency(DIRECTION, channel, 1_090_000_000.0, ()).unwrap();
+        d.set_frequency(DIRECTION, channel, FREQUENCY, ()).unwrap();
         println!("[-] frequency: {:?}", d.frequency(DIRECTION, channel));
 
-        d.set_sample_rate(DIRECTION, channel, 2_400_000.0).unwrap();
+        d.set_sample_rate(DIRECTION, channel, sample_rate).unwrap();
         println!("[-] sample rate: {:?}", d.sample_rate(DIRECTION, 0));
         channel
     } else {
@@ -143,61 +671,42 @@ fn main() {
     let mut stream = d.rx_stream::<Complex<i16>>(&[channel]).unwrap();
 
     let mut buf = vec![Complex::new(0, 0); stream.mtu().unwrap()];
-    stream.activate(None).unwrap();
 
-    // bind to listener port
-    let listener = TcpListener::bind((options.host, options.port)).unwrap();
-    listener.set_nonblocking(true).expect("Cannot set non-blocking");
+    if let Some(record) = &options.record {
+        let hw = config
+            .sdrs
+            .iter()
+            .find(|a| a.driver == options.driver)
+            .map(|sdr| {
+                let gains: Vec<String> =
+                    sdr.gain.iter().map(|g| format!("{}={}", g.key, g.value)).collect();
+                format!("{driver} gain {}", gains.join(","))
+            })
+            .unwrap_or(driver);
+        match sigmf::Recorder::create(record, sample_rate, FREQUENCY, hw, SystemTime::now()) {
+            Ok(recorder) => pipeline.recorder = Some(recorder),
+            Err(e) => {
+                println!("[!] exiting: could not record to {}: {e}", record.display());
+                std::process::exit(1);
+            }
+        }
+    }
 
-    let mut sockets = vec![];
+    stream.activate(None).unwrap();
 
     loop {
-        // add more clients
-        if let Ok((s, _addr)) = listener.accept() {
-            sockets.push(s);
-        }
-
         // try and read from sdr device
         match stream.read(&mut [&mut buf], 5_000_000) {
             Ok(len) => {
                 //utils::save_test_data(&buf[..len]);
                 // demodulate new data
                 let buf = &buf[..len];
-                let outbuf = utils::to_mag(buf);
-                let resulting_data = demodulate2400(&outbuf).unwrap();
-
-                // send new data to connected clients
-                if !resulting_data.is_empty() {
-                    let resulting_data: Vec<String> = resulting_data
-                        .iter()
-                        .map(|a| {
-                            let msg = a.buffer();
-                            let h = hex::encode(msg);
-                            let a = format!("*{h};\n");
-                            if !options.quiet {
-                                println!("{}", &a[..a.len() - 1]);
-                            }
-                            a
-                        })
-                        .collect();
-
-                    let mut remove_indexs = vec![];
-                    for (i, mut socket) in &mut sockets.iter().enumerate() {
-                        for msg in &resulting_data {
-                            // write, or add to remove list if ConnectionReset
-                            if let Err(e) = socket.write_all(msg.as_bytes())
-                                && e.kind() == std::io::ErrorKind::ConnectionReset
-                            {
-                                remove_indexs.push(i);
-                                break;
-                            }
-                        }
-                    }
-
-                    // remove
-                    for i in remove_indexs {
-                        sockets.remove(i);
-                    }
+                // the read returns when the last sample arrives, go back to the first
+                let sys_timestamp =
+                    SystemTime::now() - Duration::from_secs_f64(len as f64 / sample_rate);
+                if let Err(e) = pipeline.process(buf, sys_timestamp) {
+                    println!("[!] exiting: could not process SDR samples: {e}");
+                    std::process::exit(1);
                 }
             }
             Err(e) => {
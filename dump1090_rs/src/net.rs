@@ -0,0 +1,845 @@
+use std::fmt;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{
+    IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs,
+    UdpSocket,
+};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+use libdump1090_rs::net_io::{AvrDecoder, BeastDecoder, Frame};
+use socket2::SockRef;
+
+/// Batches of messages queued for a client before new ones are dropped, one batch per sdr read
+const CLIENT_QUEUE_LEN: usize = 64;
+/// Time the queue of a client may stay full before the client is disconnected
+const CLIENT_MAX_LAG: Duration = Duration::from_secs(10);
+/// Time a single write may block before the client is treated as gone
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// First wait before dialing a connector again, doubled after every failure
+const CONNECTOR_BACKOFF_MIN: Duration = Duration::from_secs(1);
+/// Longest wait before dialing a connector again
+const CONNECTOR_BACKOFF_MAX: Duration = Duration::from_secs(60);
+/// Time a connector waits for the remote host to accept
+const CONNECTOR_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Id of the next client of any server, for telling clients apart in the log
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Output formats, each served on its own port and available to connectors and UDP sinks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// `*<hex>;` lines
+    Avr,
+    /// Beast binary frames
+    Beast,
+    /// SBS/BaseStation lines
+    Sbs,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "avr" | "raw" => Ok(Self::Avr),
+            "beast" => Ok(Self::Beast),
+            "sbs" => Ok(Self::Sbs),
+            _ => Err(format!("unknown output format {s}, expected one of avr, beast, sbs")),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Avr => "avr",
+            Self::Beast => "beast",
+            Self::Sbs => "sbs",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// Remote host to push one output format to, from `host:port:format`, for connectors and UDP
+/// sinks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteSpec {
+    /// Host name or address, without the brackets of an IPv6 address
+    pub host: String,
+    pub port: u16,
+    pub format: OutputFormat,
+}
+
+impl FromStr for RemoteSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // from the right, as an IPv6 host holds colons of its own
+        let mut parts = s.rsplitn(3, ':');
+        let (Some(format), Some(port), Some(host)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(format!("invalid remote {s}, expected host:port:format"));
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(format!("invalid remote {s}, missing host"));
+        }
+        let port = port.parse().map_err(|e| format!("invalid port {port}: {e}"))?;
+        Ok(Self { host: host.to_string(), port, format: format.parse()? })
+    }
+}
+
+impl fmt::Display for RemoteSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}:{}", self.host, self.port, self.format)
+        } else {
+            write!(f, "{}:{}:{}", self.host, self.port, self.format)
+        }
+    }
+}
+
+/// Listener and connected clients for one output port
+///
+/// Each client is written from its own thread through a bounded queue, so a slow client never
+/// blocks the sdr reads. Messages for a client with a full queue are dropped and counted.
+pub struct Server {
+    /// name of the output format, for the log
+    name: &'static str,
+    listener: TcpListener,
+    clients: Vec<Client>,
+    max_clients: usize,
+    queue_len: usize,
+    max_lag: Duration,
+}
+
+/// Connected client, with the queue read by its writer thread
+struct Client {
+    id: u64,
+    addr: SocketAddr,
+    /// handle for shutting down the socket under the writer and reader threads
+    stream: TcpStream,
+    queue: SyncSender<Arc<[u8]>>,
+    /// writer thread, returning the error that ended it
+    writer: JoinHandle<std::io::Result<()>>,
+    /// reader thread, discarding anything sent by the client and finishing once it disconnects
+    reader: JoinHandle<std::io::Result<()>>,
+    /// count of messages dropped while the queue was full
+    dropped: u64,
+    /// time the queue was first found full, since the last batch was queued
+    full_since: Option<Instant>,
+    /// the queue stayed full for too long
+    lagging: bool,
+}
+
+impl Server {
+    /// Bind a non-blocking listener to `host`:`port`, for at most `max_clients` clients of the
+    /// output format `name`
+    pub fn bind(
+        name: &'static str,
+        host: IpAddr,
+        port: u16,
+        max_clients: usize,
+    ) -> std::io::Result<Self> {
+        let listener = TcpListener::bind((host, port))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            name,
+            listener,
+            clients: vec![],
+            max_clients,
+            queue_len: CLIENT_QUEUE_LEN,
+            max_lag: CLIENT_MAX_LAG,
+        })
+    }
+
+    /// Remove disconnected clients, then accept all waiting clients up to `max_clients`,
+    /// starting a writer thread for each
+    pub fn accept(&mut self) {
+        self.remove_disconnected();
+        while let Ok((stream, addr)) = self.listener.accept() {
+            if self.clients.len() >= self.max_clients {
+                println!(
+                    "[!] {} client {addr} refused: {} clients connected",
+                    self.name, self.max_clients
+                );
+                continue;
+            }
+            let id = NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed);
+            match Client::spawn(id, stream, addr, self.queue_len) {
+                Ok(client) => {
+                    println!("[-] {} client {id} connected from {addr}", self.name);
+                    self.clients.push(client);
+                }
+                Err(e) => println!("[!] {} client {addr} refused: {e}", self.name),
+            }
+        }
+    }
+
+    /// Queue every message for all connected clients, without waiting for any of them
+    pub fn broadcast(&mut self, msgs: &[Vec<u8>]) {
+        if msgs.is_empty() || self.clients.is_empty() {
+            return;
+        }
+        let batch: Arc<[u8]> = msgs.concat().into();
+        let now = Instant::now();
+        for client in &mut self.clients {
+            client.send(&batch, msgs.len() as u64, now, self.max_lag);
+        }
+        self.remove_disconnected();
+    }
+
+    /// Remove the clients with a stopped writer or reader thread, or a full queue for too long
+    fn remove_disconnected(&mut self) {
+        let disconnected =
+            |c: &mut Client| c.lagging || c.writer.is_finished() || c.reader.is_finished();
+        for client in self.clients.extract_if(.., disconnected) {
+            let (id, addr, dropped) = (client.id, client.addr, client.dropped);
+            let reason = client.close();
+            println!(
+                "[-] {} client {id} {addr} disconnected: {reason}, {dropped} messages dropped",
+                self.name
+            );
+        }
+    }
+}
+
+impl Client {
+    fn spawn(
+        id: u64,
+        stream: TcpStream,
+        addr: SocketAddr,
+        queue_len: usize,
+    ) -> std::io::Result<Self> {
+        // accepted sockets inherit the non-blocking listener on some platforms
+        stream.set_nonblocking(false)?;
+        stream.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT))?;
+        let writer = stream.try_clone()?;
+        let reader = stream.try_clone()?;
+        let (queue, batches) = std::sync::mpsc::sync_channel(queue_len);
+        let writer = std::thread::Builder::new()
+            .name(format!("client {id}"))
+            .spawn(move || write_batches(writer, &batches))?;
+        // a client closing during a quiet period is only seen by reading
+        let reader = std::thread::Builder::new()
+            .name(format!("client {id} reader"))
+            .spawn(move || std::io::copy(&mut &reader, &mut std::io::sink()).map(drop))?;
+        Ok(Self {
+            id,
+            addr,
+            stream,
+            queue,
+            writer,
+            reader,
+            dropped: 0,
+            full_since: None,
+            lagging: false,
+        })
+    }
+
+    /// Queue `batch` of `msgs` messages, dropping it if the queue is full
+    fn send(&mut self, batch: &Arc<[u8]>, msgs: u64, now: Instant, max_lag: Duration) {
+        match self.queue.try_send(Arc::clone(batch)) {
+            Ok(()) => self.full_since = None,
+            Err(TrySendError::Full(_)) => {
+                self.dropped += msgs;
+                let full_since = *self.full_since.get_or_insert(now);
+                self.lagging = now.duration_since(full_since) >= max_lag;
+            }
+            // the writer thread stopped, and is removed with its error
+            Err(TrySendError::Disconnected(_)) => (),
+        }
+    }
+
+    /// Stop the writer and reader threads, returning why the client is disconnected
+    fn close(self) -> String {
+        // fails the write the writer thread may be blocked on, and ends the read
+        let _ = self.stream.shutdown(Shutdown::Both);
+        if self.lagging {
+            // the writer thread is left to finish alone
+            return "too far behind".to_string();
+        }
+        drop(self.queue);
+        match (self.writer.join(), self.reader.join()) {
+            (Ok(Err(e)), _) | (_, Ok(Err(e))) => e.to_string(),
+            (Err(_), _) | (_, Err(_)) => "client thread panicked".to_string(),
+            (Ok(Ok(())), Ok(Ok(()))) => "closed".to_string(),
+        }
+    }
+}
+
+/// Outbound connection pushing one output format to a remote host
+///
+/// A thread dials the host, reconnecting with exponential backoff whenever the connection fails.
+/// Messages are discarded while not connected, or while the queue is full.
+pub struct Connector {
+    spec: RemoteSpec,
+    queue: SyncSender<Arc<[u8]>>,
+}
+
+impl Connector {
+    /// Start dialing `spec`
+    pub fn spawn(spec: RemoteSpec) -> std::io::Result<Self> {
+        Self::spawn_with_backoff(spec, CONNECTOR_BACKOFF_MIN, CONNECTOR_BACKOFF_MAX)
+    }
+
+    fn spawn_with_backoff(
+        spec: RemoteSpec,
+        backoff_min: Duration,
+        backoff_max: Duration,
+    ) -> std::io::Result<Self> {
+        let (queue, batches) = std::sync::mpsc::sync_channel(CLIENT_QUEUE_LEN);
+        let thread_spec = spec.clone();
+        std::thread::Builder::new().name(format!("connector {spec}")).spawn(move || {
+            connect_loop(&thread_spec, &batches, backoff_min, backoff_max);
+        })?;
+        Ok(Self { spec, queue })
+    }
+
+    #[must_use]
+    pub fn format(&self) -> OutputFormat {
+        self.spec.format
+    }
+
+    /// Queue every message for the remote host, without waiting for it
+    pub fn broadcast(&self, msgs: &[Vec<u8>]) {
+        if !msgs.is_empty() {
+            let _ = self.queue.try_send(msgs.concat().into());
+        }
+    }
+}
+
+/// Dial `spec` and write batches to it until the queue closes, reconnecting on any failure
+fn connect_loop(
+    spec: &RemoteSpec,
+    batches: &Receiver<Arc<[u8]>>,
+    backoff_min: Duration,
+    backoff_max: Duration,
+) {
+    let mut backoff = backoff_min;
+    loop {
+        match dial(spec) {
+            Ok(stream) => {
+                println!("[-] connector {spec} connected");
+                backoff = backoff_min;
+                // messages queued while not connected are stale
+                while batches.try_recv().is_ok() {}
+                match write_batches(stream, batches) {
+                    Ok(()) => return,
+                    Err(e) => println!("[!] connector {spec} disconnected: {e}"),
+                }
+            }
+            Err(e) => println!("[!] connector {spec} failed: {e}, retrying in {backoff:?}"),
+        }
+
+        // discard messages until it is time to dial again
+        let retry = Instant::now() + backoff;
+        loop {
+            match batches.recv_timeout(retry.saturating_duration_since(Instant::now())) {
+                Ok(_) => (),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        backoff = (backoff * 2).min(backoff_max);
+    }
+}
+
+/// Connect to the first address of `spec` that accepts
+fn dial(spec: &RemoteSpec) -> std::io::Result<TcpStream> {
+    let mut last_error = None;
+    for addr in (spec.host.as_str(), spec.port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, CONNECTOR_CONNECT_TIMEOUT) {
+            Ok(stream) => {
+                stream.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT))?;
+                return Ok(stream);
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| std::io::Error::other("no address for host")))
+}
+
+/// Datagram output of one format to a unicast or multicast address, one message per datagram
+pub struct UdpSink {
+    spec: RemoteSpec,
+    socket: UdpSocket,
+    addr: SocketAddr,
+    /// the last send failed, so the next failure isn't logged again
+    failing: bool,
+}
+
+impl UdpSink {
+    /// Resolve `spec` and bind a socket sending to it from `interface`, or from any address
+    ///
+    /// `ttl` is the time to live of IPv4 datagrams and the hop limit of IPv6 ones, with the system
+    /// default when `None`. IPv4 multicast datagrams leave through the interface holding
+    /// `interface`, IPv6 ones through the route of the bound address.
+    pub fn bind(
+        spec: RemoteSpec,
+        interface: Option<IpAddr>,
+        ttl: Option<u32>,
+    ) -> std::io::Result<Self> {
+        let addr = (spec.host.as_str(), spec.port)
+            .to_socket_addrs()?
+            .find(|addr| interface.is_none_or(|ip| ip.is_ipv4() == addr.is_ipv4()))
+            .ok_or_else(|| std::io::Error::other(format!("no address for {spec}")))?;
+        let local = interface.unwrap_or(match addr {
+            SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+            SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+        });
+        let socket = UdpSocket::bind((local, 0))?;
+        let options = SockRef::from(&socket);
+        if let Some(IpAddr::V4(interface)) = interface
+            && addr.ip().is_multicast()
+        {
+            options.set_multicast_if_v4(&interface)?;
+        }
+        if let Some(ttl) = ttl {
+            match (addr.is_ipv4(), addr.ip().is_multicast()) {
+                (true, true) => options.set_multicast_ttl_v4(ttl)?,
+                (true, false) => options.set_ttl_v4(ttl)?,
+                (false, true) => options.set_multicast_hops_v6(ttl)?,
+                (false, false) => options.set_unicast_hops_v6(ttl)?,
+            }
+        }
+        println!("[-] udp {spec} sending to {addr} from {}", socket.local_addr()?);
+        Ok(Self { spec, socket, addr, failing: false })
+    }
+
+    #[must_use]
+    pub fn format(&self) -> OutputFormat {
+        self.spec.format
+    }
+
+    /// Send each message as its own datagram, logging when sending starts and stops failing
+    pub fn broadcast(&mut self, msgs: &[Vec<u8>]) {
+        for msg in msgs {
+            match self.socket.send_to(msg, self.addr) {
+                Ok(_) if self.failing => {
+                    println!("[-] udp {} sending again", self.spec);
+                    self.failing = false;
+                }
+                Ok(_) => (),
+                Err(e) if !self.failing => {
+                    println!("[!] udp {} failed: {e}", self.spec);
+                    self.failing = true;
+                }
+                Err(_) => (),
+            }
+        }
+    }
+}
+
+/// Write batches to `stream` until the queue closes or a write fails
+fn write_batches(mut stream: TcpStream, batches: &Receiver<Arc<[u8]>>) -> std::io::Result<()> {
+    let result = batches.iter().try_for_each(|batch| stream.write_all(&batch));
+    let _ = stream.shutdown(Shutdown::Both);
+    result
+}
+
+/// Input formats read from other receivers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    /// `*<hex>;` lines, optionally with a timestamp and signal
+    Avr,
+    /// Beast binary frames
+    Beast,
+}
+
+/// Frames read from one input client at once
+pub struct InputBatch {
+    pub frames: Vec<Frame>,
+    /// Wall clock time the frames were read
+    pub sys_timestamp: SystemTime,
+}
+
+/// Listener for one input port, reading frames from other receivers
+///
+/// Each client is read from its own thread, which sends the frames to the one queue shared by
+/// all inputs. Reading blocks while the queue is full, which slows the clients down through TCP.
+pub struct InputServer {
+    /// name of the input format, for the log
+    name: &'static str,
+    format: InputFormat,
+    listener: TcpListener,
+    clients: Vec<InputClient>,
+    max_clients: usize,
+    queue: SyncSender<InputBatch>,
+}
+
+/// Connected input client, with its reader thread
+struct InputClient {
+    id: u64,
+    addr: SocketAddr,
+    reader: JoinHandle<ReadSummary>,
+}
+
+/// Counts of a finished reader thread, and the error that ended it
+struct ReadSummary {
+    frames: u64,
+    malformed: u64,
+    result: std::io::Result<()>,
+}
+
+impl InputServer {
+    pub fn bind(
+        format: InputFormat,
+        host: IpAddr,
+        port: u16,
+        max_clients: usize,
+        queue: SyncSender<InputBatch>,
+    ) -> std::io::Result<Self> {
+        let listener = TcpListener::bind((host, port))?;
+        listener.set_nonblocking(true)?;
+        let name = match format {
+            InputFormat::Avr => "avr input",
+            InputFormat::Beast => "beast input",
+        };
+        Ok(Self { name, format, listener, clients: vec![], max_clients, queue })
+    }
+
+    /// Remove disconnected clients, then accept all waiting clients up to `max_clients`,
+    /// starting a reader thread for each
+    pub fn accept(&mut self) {
+        for client in self.clients.extract_if(.., |c| c.reader.is_finished()) {
+            let (id, addr) = (client.id, client.addr);
+            let Ok(summary) = client.reader.join() else {
+                println!("[!] {} client {id} {addr} disconnected: reader panicked", self.name);
+                continue;
+            };
+            let reason = summary.result.map_or_else(|e| e.to_string(), |()| "closed".to_string());
+            println!(
+                "[-] {} client {id} {addr} disconnected: {reason}, {} frames, {} malformed",
+                self.name, summary.frames, summary.malformed
+            );
+        }
+
+        while let Ok((stream, addr)) = self.listener.accept() {
+            if self.clients.len() >= self.max_clients {
+                println!(
+                    "[!] {} client {addr} refused: {} clients connected",
+                    self.name, self.max_clients
+                );
+                continue;
+            }
+            let id = NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed);
+            let (format, queue) = (self.format, self.queue.clone());
+            let reader = stream.set_nonblocking(false).and_then(|()| {
+                std::thread::Builder::new()
+                    .name(format!("input client {id}"))
+                    .spawn(move || read_frames(stream, format, &queue))
+            });
+            match reader {
+                Ok(reader) => {
+                    println!("[-] {} client {id} connected from {addr}", self.name);
+                    self.clients.push(InputClient { id, addr, reader });
+                }
+                Err(e) => println!("[!] {} client {addr} refused: {e}", self.name),
+            }
+        }
+    }
+}
+
+/// Parse frames from `stream` into `queue` until the client or the queue closes
+fn read_frames(
+    mut stream: TcpStream,
+    format: InputFormat,
+    queue: &SyncSender<InputBatch>,
+) -> ReadSummary {
+    let mut beast = BeastDecoder::new();
+    let mut avr = AvrDecoder::new();
+    let mut summary = ReadSummary { frames: 0, malformed: 0, result: Ok(()) };
+    let mut buf = [0; 4096];
+    loop {
+        let len = match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => {
+                summary.result = Err(e);
+                break;
+            }
+        };
+        let sys_timestamp = SystemTime::now();
+        let results = match format {
+            InputFormat::Avr => avr.decode(&buf[..len]),
+            InputFormat::Beast => beast.decode(&buf[..len]),
+        };
+        let mut frames = Vec::with_capacity(results.len());
+        for result in results {
+            match result {
+                Ok(frame) => frames.push(frame),
+                Err(_) => summary.malformed += 1,
+            }
+        }
+        if frames.is_empty() {
+            continue;
+        }
+        summary.frames += frames.len() as u64;
+        if queue.send(InputBatch { frames, sys_timestamp }).is_err() {
+            break;
+        }
+    }
+    let _ = stream.shutdown(Shutdown::Both);
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connect(server: &mut Server) -> TcpStream {
+        let port = server.listener.local_addr().unwrap().port();
+        let stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+        let addr = stream.local_addr().unwrap();
+        while !server.clients.iter().any(|c| c.addr == addr) {
+            server.accept();
+        }
+        stream
+    }
+
+    #[test]
+    fn broadcast() {
+        let mut server = Server::bind("avr", Ipv4Addr::LOCALHOST.into(), 0, 8).unwrap();
+        let mut client = connect(&mut server);
+        server.broadcast(&[b"*8d;\n".to_vec(), b"*5d;\n".to_vec()]);
+
+        let mut buf = [0; 10];
+        client.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"*8d;\n*5d;\n");
+    }
+
+    #[test]
+    fn stalled_client() {
+        let mut server = Server::bind("avr", Ipv4Addr::LOCALHOST.into(), 0, 8).unwrap();
+        server.queue_len = 2;
+        server.max_lag = Duration::from_millis(200);
+        // never read, until the socket buffers and the queue are full
+        let _stalled = connect(&mut server);
+        let mut reader = connect(&mut server);
+        let mut received = 0;
+
+        let msg = vec![0_u8; 1 << 20];
+        let start = Instant::now();
+        let mut dropped = 0;
+        while server.clients.len() == 2 {
+            assert!(start.elapsed() < Duration::from_secs(10), "stalled client never dropped");
+            dropped = server.clients[0].dropped;
+            server.broadcast(std::slice::from_ref(&msg));
+            // the other client keeps up
+            let mut buf = vec![0_u8; msg.len()];
+            reader.read_exact(&mut buf).unwrap();
+            received += 1;
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(received > 2);
+        assert!(dropped > 0);
+
+        // the remaining client is unaffected
+        assert_eq!(server.clients[0].addr, reader.local_addr().unwrap());
+        assert_eq!(server.clients[0].dropped, 0);
+        server.broadcast(std::slice::from_ref(&msg));
+        let mut buf = vec![0_u8; msg.len()];
+        reader.read_exact(&mut buf).unwrap();
+    }
+
+    #[test]
+    fn disconnected_clients() {
+        let mut server = Server::bind("beast", Ipv4Addr::LOCALHOST.into(), 0, 8).unwrap();
+        let first = connect(&mut server);
+        let second = connect(&mut server);
+        let mut third = connect(&mut server);
+        let ids: Vec<u64> = server.clients.iter().map(|c| c.id).collect();
+        assert!(ids[0] < ids[1] && ids[1] < ids[2]);
+
+        // two clients gone at once, while broadcasting to the remaining one
+        drop(first);
+        drop(second);
+        let start = Instant::now();
+        while server.clients.len() > 1 {
+            assert!(start.elapsed() < Duration::from_secs(10), "closed clients never removed");
+            server.broadcast(&[b"*8d;\n".to_vec()]);
+            let mut buf = [0; 5];
+            third.read_exact(&mut buf).unwrap();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(server.clients[0].id, ids[2]);
+    }
+
+    #[test]
+    fn max_clients() {
+        let mut server = Server::bind("sbs", Ipv4Addr::LOCALHOST.into(), 0, 1).unwrap();
+        let _first = connect(&mut server);
+        let port = server.listener.local_addr().unwrap().port();
+        let mut refused = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+        refused.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        server.accept();
+        assert_eq!(server.clients.len(), 1);
+
+        // closed by the server without any data
+        let mut buf = [0; 1];
+        assert!(matches!(refused.read(&mut buf), Ok(0) | Err(_)));
+    }
+
+    #[test]
+    fn closed_client_without_broadcast() {
+        let mut server = Server::bind("sbs", Ipv4Addr::LOCALHOST.into(), 0, 1).unwrap();
+        let first = connect(&mut server);
+
+        // the slot is freed without any write to the closed client
+        drop(first);
+        let start = Instant::now();
+        while !server.clients.is_empty() {
+            assert!(start.elapsed() < Duration::from_secs(10), "closed client never removed");
+            server.accept();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let _second = connect(&mut server);
+        assert_eq!(server.clients.len(), 1);
+    }
+
+    #[test]
+    fn remote_spec() {
+        let spec: RemoteSpec = "feed.example.com:30004:beast".parse().unwrap();
+        assert_eq!(spec.host, "feed.example.com");
+        assert_eq!(spec.port, 30004);
+        assert_eq!(spec.format, OutputFormat::Beast);
+        assert_eq!(spec.to_string(), "feed.example.com:30004:beast");
+
+        let spec: RemoteSpec = "[::1]:30002:AVR".parse().unwrap();
+        assert_eq!(spec.host, "::1");
+        assert_eq!(spec.format, OutputFormat::Avr);
+        assert_eq!(spec.to_string(), "[::1]:30002:avr");
+
+        assert!("localhost:30004".parse::<RemoteSpec>().is_err());
+        assert!("localhost:port:beast".parse::<RemoteSpec>().is_err());
+        assert!("localhost:30004:json".parse::<RemoteSpec>().is_err());
+        assert!(":30004:beast".parse::<RemoteSpec>().is_err());
+    }
+
+    #[test]
+    fn connector_reconnects() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let spec = RemoteSpec { host: "localhost".to_string(), port, format: OutputFormat::Avr };
+        let connector = Connector::spawn_with_backoff(
+            spec,
+            Duration::from_millis(10),
+            Duration::from_millis(40),
+        )
+        .unwrap();
+
+        // sends once connected, then again after the collector drops the connection, which the
+        // connector only notices when writing
+        listener.set_nonblocking(true).unwrap();
+        for _ in 0..2 {
+            let mut stream = loop {
+                match listener.accept() {
+                    Ok((stream, _)) => break stream,
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                        connector.broadcast(&[b"*8d;\n".to_vec()]);
+                        std::thread::sleep(Duration::from_millis(5));
+                    }
+                    Err(e) => panic!("{e}"),
+                }
+            };
+            stream.set_nonblocking(false).unwrap();
+            stream.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+            let mut buf = [0; 5];
+            // messages are discarded until the connector has seen the connection
+            while stream.read_exact(&mut buf).is_err() {
+                connector.broadcast(&[b"*8d;\n".to_vec()]);
+            }
+            assert_eq!(&buf, b"*8d;\n");
+        }
+    }
+
+    #[test]
+    fn udp_sink() {
+        let collector = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        collector.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let port = collector.local_addr().unwrap().port();
+        let spec = RemoteSpec { host: "localhost".to_string(), port, format: OutputFormat::Avr };
+        let mut sink = UdpSink::bind(spec, Some(Ipv4Addr::LOCALHOST.into()), Some(2)).unwrap();
+        assert_eq!(sink.socket.ttl().unwrap(), 2);
+
+        // one datagram per message
+        sink.broadcast(&[b"*8d;\n".to_vec(), b"*5d0f;\n".to_vec()]);
+        let mut buf = [0; 64];
+        let (len, from) = collector.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"*8d;\n");
+        assert_eq!(from.ip(), Ipv4Addr::LOCALHOST);
+        let len = collector.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"*5d0f;\n");
+
+        // the interface must have an address of the family of the destination
+        let spec = RemoteSpec { host: "127.0.0.1".to_string(), port, format: OutputFormat::Avr };
+        assert!(UdpSink::bind(spec, Some(Ipv6Addr::LOCALHOST.into()), None).is_err());
+
+        // the hop limit of IPv6 datagrams
+        let spec = RemoteSpec { host: "::1".to_string(), port, format: OutputFormat::Avr };
+        let sink = UdpSink::bind(spec, None, Some(3)).unwrap();
+        assert_eq!(SockRef::from(&sink.socket).unicast_hops_v6().unwrap(), 3);
+    }
+
+    #[test]
+    fn udp_sink_multicast() {
+        let group = Ipv4Addr::new(239, 255, 109, 71);
+        let collector = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).unwrap();
+        collector.join_multicast_v4(&group, &Ipv4Addr::LOCALHOST).unwrap();
+        collector.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let port = collector.local_addr().unwrap().port();
+
+        // sent through the loopback interface, whatever the default route
+        let spec = RemoteSpec { host: group.to_string(), port, format: OutputFormat::Beast };
+        let mut sink = UdpSink::bind(spec, Some(Ipv4Addr::LOCALHOST.into()), Some(4)).unwrap();
+        let options = SockRef::from(&sink.socket);
+        assert_eq!(options.multicast_if_v4().unwrap(), Ipv4Addr::LOCALHOST);
+        assert_eq!(options.multicast_ttl_v4().unwrap(), 4);
+
+        sink.broadcast(&[b"\x1a2".to_vec()]);
+        let mut buf = [0; 64];
+        let (len, from) = collector.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"\x1a2");
+        assert_eq!(from.ip(), Ipv4Addr::LOCALHOST);
+    }
+
+    #[test]
+    fn input_server() {
+        let (queue, batches) = std::sync::mpsc::sync_channel(4);
+        let mut input =
+            InputServer::bind(InputFormat::Avr, Ipv4Addr::LOCALHOST.into(), 0, 1, queue).unwrap();
+        let port = input.listener.local_addr().unwrap().port();
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+        while input.clients.is_empty() {
+            input.accept();
+        }
+
+        // frames split across writes, with a malformed line between them
+        stream.write_all(b"*5d0f;\nhello\n*8dad9293").unwrap();
+        stream.flush().unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        stream.write_all(b"58b9c6273f002169c02e;\n").unwrap();
+        let mut frames = vec![];
+        while frames.len() < 2 {
+            let batch = batches.recv_timeout(Duration::from_secs(5)).unwrap();
+            frames.extend(batch.frames);
+        }
+        assert_eq!(frames[0].data, [0x5d, 0x0f]);
+        assert_eq!(frames[1].data.len(), 14);
+
+        // the reader stops with the client, and is removed with its counts
+        drop(stream);
+        while !input.clients.is_empty() {
+            input.accept();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
@@ -11,6 +11,9 @@ pub struct Sdr {
     #[serde(default = "Sdr::default_channel")]
     pub channel: usize,
     pub driver: String,
+    /// Sample rate in Hz: 2.0MHz, 2.4MHz, 6MHz, 8MHz or 12MHz
+    #[serde(default = "Sdr::default_sample_rate")]
+    pub sample_rate: f64,
     pub setting: Option<Vec<Arg>>,
     pub gain: Vec<Gain>,
     pub antenna: Option<Antenna>,
@@ -20,6 +23,10 @@ impl Sdr {
     pub fn default_channel() -> usize {
         0
     }
+
+    pub fn default_sample_rate() -> f64 {
+        2_400_000.0
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -48,4 +55,23 @@ mod tests {
         // ensure that the include_str config compiles to an SdrConfig
         let _: SdrConfig = toml::from_str(DEFAULT_CONFIG).unwrap();
     }
+
+    #[test]
+    fn sample_rate() {
+        let config: SdrConfig = toml::from_str(
+            r#"
+            [[sdrs]]
+            driver = "rtlsdr"
+            sample_rate = 2_000_000.0
+            gain = []
+
+            [[sdrs]]
+            driver = "hackrf"
+            gain = []
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.sdrs[0].sample_rate, 2_000_000.0);
+        assert_eq!(config.sdrs[1].sample_rate, 2_400_000.0);
+    }
 }
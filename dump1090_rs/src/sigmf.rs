@@ -0,0 +1,332 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use libdump1090_rs::convert::SampleFormat;
+use libdump1090_rs::demod_2400::ModeSMessage;
+use num_complex::Complex;
+use serde::{Deserialize, Serialize};
+
+pub const DATA_EXTENSION: &str = "sigmf-data";
+pub const META_EXTENSION: &str = "sigmf-meta";
+
+const SIGMF_VERSION: &str = "1.0.0";
+
+/// Length of the preamble in microseconds
+const PREAMBLE_US: usize = 8;
+
+/// End of the meta file written by [`Recorder`], after the last annotation
+const META_END: &[u8] = b"\n  ]\n}\n";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Meta {
+    pub global: Global,
+    #[serde(default)]
+    pub captures: Vec<Capture>,
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Global {
+    #[serde(rename = "core:datatype")]
+    pub datatype: String,
+    #[serde(rename = "core:sample_rate", skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<f64>,
+    #[serde(rename = "core:version")]
+    pub version: String,
+    #[serde(rename = "core:hw", skip_serializing_if = "Option::is_none")]
+    pub hw: Option<String>,
+    #[serde(rename = "core:recorder", skip_serializing_if = "Option::is_none")]
+    pub recorder: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Capture {
+    #[serde(rename = "core:sample_start")]
+    pub sample_start: u64,
+    #[serde(rename = "core:frequency", skip_serializing_if = "Option::is_none")]
+    pub frequency: Option<f64>,
+    #[serde(rename = "core:datetime", skip_serializing_if = "Option::is_none")]
+    pub datetime: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Annotation {
+    #[serde(rename = "core:sample_start")]
+    pub sample_start: u64,
+    #[serde(rename = "core:sample_count", skip_serializing_if = "Option::is_none")]
+    pub sample_count: Option<u64>,
+    #[serde(rename = "core:label", skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+impl Meta {
+    /// Sample format of the data file
+    pub fn format(&self) -> std::io::Result<SampleFormat> {
+        match self.global.datatype.as_str() {
+            "cu8" => Ok(SampleFormat::Cu8),
+            "ci8" => Ok(SampleFormat::Cs8),
+            "ci16_le" => Ok(SampleFormat::Cs16),
+            "cf32_le" => Ok(SampleFormat::Cf32),
+            datatype => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("unsupported sigmf datatype {datatype}"),
+            )),
+        }
+    }
+
+    /// Wall clock time of the first sample, if recorded
+    pub fn start_time(&self) -> Option<SystemTime> {
+        self.captures.first()?.datetime.as_deref().and_then(parse_datetime)
+    }
+}
+
+/// Whether `path` is one of the two files of a recording
+pub fn is_sigmf(path: &Path) -> bool {
+    matches!(path.extension().and_then(|e| e.to_str()), Some(DATA_EXTENSION | META_EXTENSION))
+}
+
+/// Data and meta file paths of the recording at `path`, with or without extension
+pub fn paths(path: &Path) -> (PathBuf, PathBuf) {
+    let base = if is_sigmf(path) { path.with_extension("") } else { path.to_path_buf() };
+    let with = |ext: &str| {
+        let mut p = base.clone().into_os_string();
+        p.push(".");
+        p.push(ext);
+        PathBuf::from(p)
+    };
+    (with(DATA_EXTENSION), with(META_EXTENSION))
+}
+
+pub fn read_meta(path: &Path) -> std::io::Result<Meta> {
+    let meta = std::fs::read_to_string(path)?;
+    serde_json::from_str(&meta).map_err(std::io::Error::other)
+}
+
+/// Writes `ci16_le` samples from the sdr, and annotates each decoded message
+///
+/// The annotations are appended to the meta file, one per line, over its closing brackets
+/// which are written again after them, so the meta file is valid after each [`Self::flush`].
+pub struct Recorder {
+    data: BufWriter<File>,
+    meta: File,
+    /// position of the end of the last annotation in the meta file
+    annotations_end: u64,
+    annotations: usize,
+    /// annotations added since the meta file was last written
+    pending: Vec<u8>,
+    sample_rate: f64,
+}
+
+impl Recorder {
+    pub fn create(
+        path: &Path,
+        sample_rate: f64,
+        frequency: f64,
+        hw: String,
+        start: SystemTime,
+    ) -> std::io::Result<Self> {
+        let (data_path, meta_path) = paths(path);
+        let data = BufWriter::new(File::create(&data_path)?);
+        let global = Global {
+            datatype: "ci16_le".to_string(),
+            sample_rate: Some(sample_rate),
+            version: SIGMF_VERSION.to_string(),
+            hw: Some(hw),
+            recorder: Some(format!("dump1090_rs {}", env!("CARGO_PKG_VERSION"))),
+        };
+        let captures = [Capture {
+            sample_start: 0,
+            frequency: Some(frequency),
+            datetime: Some(format_datetime(start)),
+        }];
+        let head = format!(
+            "{{\n  \"global\": {},\n  \"captures\": {},\n  \"annotations\": [",
+            serde_json::to_string(&global).map_err(std::io::Error::other)?,
+            serde_json::to_string(&captures).map_err(std::io::Error::other)?,
+        );
+        let mut meta = File::create(&meta_path)?;
+        meta.write_all(head.as_bytes())?;
+        meta.write_all(META_END)?;
+
+        println!("[-] recording to {}", data_path.display());
+        Ok(Self {
+            data,
+            meta,
+            annotations_end: head.len() as u64,
+            annotations: 0,
+            pending: vec![],
+            sample_rate,
+        })
+    }
+
+    pub fn write_samples(&mut self, samples: &[Complex<i16>]) -> std::io::Result<()> {
+        for s in samples {
+            self.data.write_all(&s.re.to_le_bytes())?;
+            self.data.write_all(&s.im.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Annotate `msg` at its sample offset from the start of the recording
+    pub fn annotate(&mut self, msg: &ModeSMessage) {
+        let bits = msg.buffer().len() * 8;
+        let sample_count = ((PREAMBLE_US + bits) as f64 * self.sample_rate / 1_000_000.0).ceil();
+        let annotation = Annotation {
+            sample_start: msg.sample_offset(),
+            sample_count: Some(sample_count as u64),
+            label: Some(hex::encode(msg.buffer())),
+        };
+        let separator: &[u8] = if self.annotations == 0 { b"\n    " } else { b",\n    " };
+        self.pending.extend_from_slice(separator);
+        serde_json::to_writer(&mut self.pending, &annotation).unwrap();
+        self.annotations += 1;
+    }
+
+    /// Flush the samples, and append the new annotations to the meta file
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.data.flush()?;
+        if !self.pending.is_empty() {
+            self.meta.seek(SeekFrom::Start(self.annotations_end))?;
+            self.meta.write_all(&self.pending)?;
+            self.meta.write_all(META_END)?;
+            self.annotations_end += self.pending.len() as u64;
+            self.pending.clear();
+        }
+        Ok(())
+    }
+}
+
+/// ISO 8601 UTC, as required by `core:datetime`
+fn format_datetime(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let days = (secs / 86_400) as i64;
+    let secs_of_day = secs % 86_400;
+
+    // days to civil date, from http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        secs_of_day / 3600,
+        (secs_of_day / 60) % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Parse `YYYY-MM-DDTHH:MM:SS[.fff]Z`
+fn parse_datetime(s: &str) -> Option<SystemTime> {
+    let s = s.strip_suffix('Z')?;
+    let (date, time) = s.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let (time, frac) = time.split_once('.').unwrap_or((time, ""));
+    let mut time = time.splitn(3, ':').map(str::parse::<u64>);
+    let (hour, minute, second) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    let nanos =
+        if frac.is_empty() { 0 } else { format!("{frac:0<9}").get(..9)?.parse::<u32>().ok()? };
+
+    // civil date to days, from http://howardhinnant.github.io/date_algorithms.html
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = u64::try_from(era * 146_097 + doe - 719_468).ok()?;
+
+    let secs = days * 86_400 + hour * 3600 + minute * 60 + second;
+    Some(UNIX_EPOCH + Duration::new(secs, nanos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn datetime() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_641_428_165_033);
+        let s = format_datetime(time);
+        assert_eq!(s, "2022-01-06T00:16:05.033Z");
+        assert_eq!(parse_datetime(&s), Some(time));
+        assert_eq!(
+            parse_datetime("2022-01-06T00:16:05Z"),
+            Some(UNIX_EPOCH + Duration::from_secs(1_641_428_165))
+        );
+        assert_eq!(parse_datetime("2022-01-06 00:16:05"), None);
+    }
+
+    #[test]
+    fn meta() {
+        let meta = r#"{
+            "global": {"core:datatype": "cu8", "core:sample_rate": 2400000, "core:version": "1.0.0"},
+            "captures": [{"core:sample_start": 0, "core:datetime": "2022-01-06T00:16:05.033Z"}],
+            "annotations": [{"core:sample_start": 14285, "core:sample_count": 288, "core:label": "8da79de99909932f780c9e2f2f8f"}]
+        }"#;
+        let meta: Meta = serde_json::from_str(meta).unwrap();
+        assert_eq!(meta.format().unwrap(), SampleFormat::Cu8);
+        assert_eq!(meta.start_time(), Some(UNIX_EPOCH + Duration::from_millis(1_641_428_165_033)));
+        assert_eq!(meta.annotations[0].sample_start, 14285);
+
+        let (data, meta) = paths(Path::new("capture.sigmf-meta"));
+        assert_eq!(data, Path::new("capture.sigmf-data"));
+        assert_eq!(meta, Path::new("capture.sigmf-meta"));
+        assert_eq!(paths(Path::new("capture")).0, Path::new("capture.sigmf-data"));
+    }
+
+    #[test]
+    fn record() {
+        let data = std::fs::read("../test_iq/test_1641428165033.iq").unwrap();
+        let samples: Vec<Complex<i16>> = data
+            .chunks_exact(4)
+            .map(|s| {
+                Complex::new(i16::from_le_bytes([s[0], s[1]]), i16::from_le_bytes([s[2], s[3]]))
+            })
+            .collect();
+
+        let path = std::env::temp_dir().join(format!("dump1090_rs_record_{}", std::process::id()));
+        let start = UNIX_EPOCH + Duration::from_secs(1_641_428_165);
+        let mut recorder =
+            Recorder::create(&path, 2_400_000.0, 1_090_000_000.0, "test".to_string(), start)
+                .unwrap();
+        recorder.write_samples(&samples).unwrap();
+        let mut demodulator = libdump1090_rs::Demodulator::new();
+        let msgs = demodulator.demodulate(&samples, start).unwrap();
+        // the meta file is valid after each flush
+        let (first, rest) = msgs.split_at(2);
+        for msg in first {
+            recorder.annotate(msg);
+        }
+        recorder.flush().unwrap();
+        let (data_path, meta_path) = paths(&path);
+        assert_eq!(read_meta(&meta_path).unwrap().annotations.len(), 2);
+        for msg in rest {
+            recorder.annotate(msg);
+        }
+        recorder.flush().unwrap();
+
+        assert_eq!(std::fs::read(&data_path).unwrap(), data);
+        let meta = read_meta(&meta_path).unwrap();
+        assert_eq!(meta.format().unwrap(), SampleFormat::Cs16);
+        assert_eq!(meta.start_time(), Some(start));
+        assert_eq!(meta.annotations.len(), msgs.len());
+        assert_eq!(meta.annotations[0].sample_start, 14_285);
+        assert_eq!(meta.annotations[0].sample_count, Some(288));
+        assert_eq!(meta.annotations[0].label.as_deref(), Some("8da79de99909932f780c9e2f2f8f"));
+
+        std::fs::remove_file(data_path).unwrap();
+        std::fs::remove_file(meta_path).unwrap();
+    }
+}
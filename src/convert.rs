@@ -0,0 +1,143 @@
+// This module includes functionality translated from convert.c
+
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{Error, MagnitudeBuffer, SampleRate};
+
+/// Cutoff frequency of the DC filter applied to `Cu8` samples, in Hz
+const DC_FILTER_CUTOFF: f64 = 1.0;
+
+/// Layout of interleaved I/Q samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SampleFormat {
+    /// 8 bit unsigned, offset by 127.5, as written by `rtl_sdr`
+    Cu8,
+    /// 8 bit signed, as written by `hackrf_transfer`
+    Cs8,
+    /// 16 bit signed little-endian, as written by `utils::save_test_data`
+    #[default]
+    Cs16,
+    /// 32 bit float little-endian, full scale at 1.0
+    Cf32,
+}
+
+impl SampleFormat {
+    /// Size of one I/Q sample pair in bytes
+    #[must_use]
+    pub const fn bytes_per_sample(self) -> usize {
+        match self {
+            Self::Cu8 | Self::Cs8 => 2,
+            Self::Cs16 => 4,
+            Self::Cf32 => 8,
+        }
+    }
+}
+
+impl FromStr for SampleFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "cu8" | "uc8" => Ok(Self::Cu8),
+            "cs8" | "sc8" => Ok(Self::Cs8),
+            "cs16" | "sc16" => Ok(Self::Cs16),
+            "cf32" | "fc32" => Ok(Self::Cf32),
+            _ => Err(Error::UnsupportedFormat(format!(
+                "sample format {s}, expected one of cu8, cs8, cs16, cf32"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for SampleFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Cu8 => "cu8",
+            Self::Cs8 => "cs8",
+            Self::Cs16 => "cs16",
+            Self::Cf32 => "cf32",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// Single pole high-pass filter removing the DC offset of `Cu8` samples
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DcFilter {
+    z1_i: f32,
+    z1_q: f32,
+    dc_a: f32,
+    dc_b: f32,
+}
+
+impl Default for DcFilter {
+    fn default() -> Self {
+        Self::new(SampleRate::default())
+    }
+}
+
+impl DcFilter {
+    pub(crate) fn new(sample_rate: SampleRate) -> Self {
+        let dc_b = (-2.0 * PI * DC_FILTER_CUTOFF / f64::from(sample_rate.hz())).exp();
+        Self { z1_i: 0.0, z1_q: 0.0, dc_a: (1.0 - dc_b) as f32, dc_b: dc_b as f32 }
+    }
+
+    #[inline(always)]
+    fn filter(&mut self, i: f32, q: f32) -> (f32, f32) {
+        self.z1_i = i.mul_add(self.dc_a, self.z1_i * self.dc_b);
+        self.z1_q = q.mul_add(self.dc_a, self.z1_q * self.dc_b);
+        (i - self.z1_i, q - self.z1_q)
+    }
+}
+
+/// Push the magnitude of full scale normalized I/Q
+#[inline(always)]
+pub(crate) fn push_mag_iq(fi: f32, fq: f32, outbuf: &mut MagnitudeBuffer) {
+    let mag_sqr = fi.mul_add(fi, fq * fq);
+    let mag = f32::sqrt(mag_sqr);
+    outbuf.push(mag.mul_add(f32::from(u16::MAX), 0.5) as u16);
+}
+
+// convert.c convert_uc8_generic, convert_sc16_generic
+/// Push the magnitudes of raw `data` in `format`, any trailing partial sample is ignored
+pub(crate) fn push_mag_bytes(
+    format: SampleFormat,
+    data: &[u8],
+    dc_filter: &mut DcFilter,
+    outbuf: &mut MagnitudeBuffer,
+) {
+    let chunks = data.chunks_exact(format.bytes_per_sample());
+    match format {
+        SampleFormat::Cu8 => {
+            for b in chunks {
+                let fi = (f32::from(b[0]) - 127.5) / 127.5;
+                let fq = (f32::from(b[1]) - 127.5) / 127.5;
+                let (fi, fq) = dc_filter.filter(fi, fq);
+                push_mag_iq(fi, fq, outbuf);
+            }
+        }
+        SampleFormat::Cs8 => {
+            for b in chunks {
+                let fi = f32::from(b[0] as i8) / 128.0;
+                let fq = f32::from(b[1] as i8) / 128.0;
+                push_mag_iq(fi, fq, outbuf);
+            }
+        }
+        SampleFormat::Cs16 => {
+            for b in chunks {
+                let fi = f32::from(i16::from_le_bytes([b[0], b[1]])) / (1 << 15) as f32;
+                let fq = f32::from(i16::from_le_bytes([b[2], b[3]])) / (1 << 15) as f32;
+                push_mag_iq(fi, fq, outbuf);
+            }
+        }
+        SampleFormat::Cf32 => {
+            for b in chunks {
+                let fi = f32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+                let fq = f32::from_le_bytes([b[4], b[5], b[6], b[7]]);
+                push_mag_iq(fi, fq, outbuf);
+            }
+        }
+    }
+}
@@ -0,0 +1,432 @@
+// This module includes functionality translated from cpr.c
+
+use std::f64::consts::PI;
+
+use crate::mode_s::CprFrame;
+
+/// Number of latitude zones between the equator and a pole
+const NZ: f64 = 15.0;
+/// Range of the 17 bit encoded latitude and longitude
+const CPR_MAX: f64 = 131_072.0;
+
+/// Mean radius of the earth in meters, as used by dump1090
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// Largest receiver range for which local decoding relative to the receiver is unambiguous, in
+/// meters, for airborne (180NM) and surface (45NM) positions
+const LOCAL_AIRBORNE_MAX_RANGE: f64 = 1852.0 * 180.0;
+const LOCAL_SURFACE_MAX_RANGE: f64 = 1852.0 * 45.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Position {
+    /// Latitude in degrees, positive north
+    pub lat: f64,
+    /// Longitude in degrees, positive east
+    pub lon: f64,
+}
+
+impl Position {
+    #[must_use]
+    pub fn new(lat: f64, lon: f64) -> Self {
+        Self { lat, lon }
+    }
+
+    // track.c greatcircle
+    /// Great circle distance to `other`, in meters
+    #[must_use]
+    pub fn distance(&self, other: &Self) -> f64 {
+        let lat0 = self.lat.to_radians();
+        let lon0 = self.lon.to_radians();
+        let lat1 = other.lat.to_radians();
+        let lon1 = other.lon.to_radians();
+
+        let dlat = (lat1 - lat0).abs();
+        let dlon = (lon1 - lon0).abs();
+
+        // use haversine for small distances for better numerical stability
+        if dlat < 0.001 && dlon < 0.001 {
+            let a =
+                (dlat / 2.0).sin().powi(2) + lat0.cos() * lat1.cos() * (dlon / 2.0).sin().powi(2);
+            return EARTH_RADIUS * 2.0 * a.sqrt().atan2((1.0 - a).sqrt());
+        }
+
+        // spherical law of cosines
+        EARTH_RADIUS * (lat0.sin() * lat1.sin() + lat0.cos() * lat1.cos() * dlon.cos()).acos()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CprError {
+    /// The encoded latitude is outside of -90..90, or the result is not within half a zone of
+    /// the reference position
+    BadData,
+    /// The even and odd positions are in different latitude zones, wait for a new pair
+    ZoneMismatch,
+    /// Surface and receiver relative decoding need a receiver location
+    NoReference,
+    /// The decoded position is further than the maximum range of the receiver
+    OutOfRange,
+}
+
+impl std::fmt::Display for CprError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BadData => write!(f, "bad CPR data"),
+            Self::ZoneMismatch => write!(f, "even and odd CPR positions in different zones"),
+            Self::NoReference => write!(f, "no reference position for CPR decoding"),
+            Self::OutOfRange => write!(f, "CPR position out of receiver range"),
+        }
+    }
+}
+
+impl std::error::Error for CprError {}
+
+#[inline(always)]
+fn cpr_mod_int(a: i32, b: i32) -> i32 {
+    a.rem_euclid(b)
+}
+
+#[inline(always)]
+fn cpr_mod_double(a: f64, b: f64) -> f64 {
+    a.rem_euclid(b)
+}
+
+/// Number of longitude zones at latitude `lat`
+fn cpr_nl_function(lat: f64) -> i32 {
+    let lat = lat.abs();
+    if lat == 0.0 {
+        return 59;
+    } else if lat == 87.0 {
+        return 2;
+    } else if lat > 87.0 {
+        return 1;
+    }
+
+    let a = 1.0 - (PI / (2.0 * NZ)).cos();
+    let b = (PI / 180.0 * lat).cos().powi(2);
+    (2.0 * PI / (1.0 - a / b).acos()).floor() as i32
+}
+
+fn cpr_n_function(lat: f64, odd: bool) -> i32 {
+    let nl = cpr_nl_function(lat) - i32::from(odd);
+    nl.max(1)
+}
+
+fn cpr_dlon_function(lat: f64, odd: bool, surface: bool) -> f64 {
+    (if surface { 90.0 } else { 360.0 }) / f64::from(cpr_n_function(lat, odd))
+}
+
+/// Latitudes of the even and odd positions, before picking the quadrant for surface positions
+fn cpr_lat_pair(even: &CprFrame, odd: &CprFrame, surface: bool) -> (f64, f64) {
+    let scale = if surface { 90.0 } else { 360.0 };
+    let dlat0 = scale / 60.0;
+    let dlat1 = scale / 59.0;
+    let lat0 = f64::from(even.lat);
+    let lat1 = f64::from(odd.lat);
+
+    // Compute the Latitude Index "j"
+    let j = (((59.0 * lat0 - 60.0 * lat1) / CPR_MAX) + 0.5).floor() as i32;
+    let rlat0 = dlat0 * (f64::from(cpr_mod_int(j, 60)) + lat0 / CPR_MAX);
+    let rlat1 = dlat1 * (f64::from(cpr_mod_int(j, 59)) + lat1 / CPR_MAX);
+    (rlat0, rlat1)
+}
+
+/// Longitude of the pair, from the latitude of the most recent frame
+fn cpr_lon(even: &CprFrame, odd: &CprFrame, rlat: f64, use_odd: bool, surface: bool) -> f64 {
+    let lon0 = f64::from(even.lon);
+    let lon1 = f64::from(odd.lon);
+
+    // Compute ni and the Longitude Index "m"
+    let nl = f64::from(cpr_nl_function(rlat));
+    let ni = cpr_n_function(rlat, use_odd);
+    let m = (((lon0 * (nl - 1.0)) - (lon1 * nl)) / CPR_MAX + 0.5).floor() as i32;
+    let lon = if use_odd { lon1 } else { lon0 };
+    cpr_dlon_function(rlat, use_odd, surface) * (f64::from(cpr_mod_int(m, ni)) + lon / CPR_MAX)
+}
+
+// cpr.c decodeCPRairborne
+/// Globally decode an airborne even/odd pair, `use_odd` selecting the most recent frame
+pub fn decode_cpr_airborne(
+    even: &CprFrame,
+    odd: &CprFrame,
+    use_odd: bool,
+) -> Result<Position, CprError> {
+    let (mut rlat0, mut rlat1) = cpr_lat_pair(even, odd, false);
+
+    if rlat0 >= 270.0 {
+        rlat0 -= 360.0;
+    }
+    if rlat1 >= 270.0 {
+        rlat1 -= 360.0;
+    }
+
+    // Check to see that the latitude is in range: -90 .. +90
+    if !(-90.0..=90.0).contains(&rlat0) || !(-90.0..=90.0).contains(&rlat1) {
+        return Err(CprError::BadData);
+    }
+
+    // Check that both are in the same latitude zone, or abort.
+    if cpr_nl_function(rlat0) != cpr_nl_function(rlat1) {
+        return Err(CprError::ZoneMismatch);
+    }
+
+    let rlat = if use_odd { rlat1 } else { rlat0 };
+    let mut rlon = cpr_lon(even, odd, rlat, use_odd, false);
+
+    // Renormalize to -180 .. +180
+    rlon -= ((rlon + 180.0) / 360.0).floor() * 360.0;
+
+    Ok(Position::new(rlat, rlon))
+}
+
+// cpr.c decodeCPRsurface
+/// Globally decode a surface even/odd pair, `use_odd` selecting the most recent frame
+///
+/// Surface positions repeat every 90 degrees, the result is the one closest to `reference`.
+pub fn decode_cpr_surface(
+    reference: &Position,
+    even: &CprFrame,
+    odd: &CprFrame,
+    use_odd: bool,
+) -> Result<Position, CprError> {
+    let (mut rlat0, mut rlat1) = cpr_lat_pair(even, odd, true);
+
+    // Pick the quadrant that's closest to the reference location, there are only two valid
+    // quadrants: -90..0 and 0..90
+    if (rlat0 - 90.0 - reference.lat).abs() < (rlat0 - reference.lat).abs() {
+        rlat0 -= 90.0;
+    }
+    if (rlat1 - 90.0 - reference.lat).abs() < (rlat1 - reference.lat).abs() {
+        rlat1 -= 90.0;
+    }
+
+    // Check to see that the latitude is in range: -90 .. +90
+    if !(-90.0..=90.0).contains(&rlat0) || !(-90.0..=90.0).contains(&rlat1) {
+        return Err(CprError::BadData);
+    }
+
+    // Check that both are in the same latitude zone, or abort.
+    if cpr_nl_function(rlat0) != cpr_nl_function(rlat1) {
+        return Err(CprError::ZoneMismatch);
+    }
+
+    let rlat = if use_odd { rlat1 } else { rlat0 };
+    let mut rlon = cpr_lon(even, odd, rlat, use_odd, true);
+
+    // Pick the quadrant that's closest to the reference location, all four are valid
+    rlon += ((reference.lon - rlon + 45.0) / 90.0).floor() * 90.0;
+
+    // Renormalize to -180 .. +180
+    rlon -= ((rlon + 180.0) / 360.0).floor() * 360.0;
+
+    Ok(Position::new(rlat, rlon))
+}
+
+// cpr.c decodeCPRrelative
+/// Locally decode a single frame, relative to a `reference` within half a zone of it
+pub fn decode_cpr_relative(
+    reference: &Position,
+    frame: &CprFrame,
+    surface: bool,
+) -> Result<Position, CprError> {
+    let fractional_lat = f64::from(frame.lat) / CPR_MAX;
+    let fractional_lon = f64::from(frame.lon) / CPR_MAX;
+
+    let dlat = (if surface { 90.0 } else { 360.0 }) / (if frame.odd { 59.0 } else { 60.0 });
+
+    // Compute the Latitude Index "j"
+    let j = (reference.lat / dlat).floor()
+        + (0.5 + cpr_mod_double(reference.lat, dlat) / dlat - fractional_lat).floor();
+    let mut rlat = dlat * (j + fractional_lat);
+    if rlat >= 270.0 {
+        rlat -= 360.0;
+    }
+
+    // Check to see that the latitude is in range: -90 .. +90
+    if !(-90.0..=90.0).contains(&rlat) {
+        return Err(CprError::BadData);
+    }
+
+    // Check to see that answer is reasonable - ie no more than 1/2 cell away
+    if (rlat - reference.lat).abs() > dlat / 2.0 {
+        return Err(CprError::BadData);
+    }
+
+    // Compute the Longitude Index "m"
+    let dlon = cpr_dlon_function(rlat, frame.odd, surface);
+    let m = (reference.lon / dlon).floor()
+        + (0.5 + cpr_mod_double(reference.lon, dlon) / dlon - fractional_lon).floor();
+    let mut rlon = dlon * (m + fractional_lon);
+    if rlon > 180.0 {
+        rlon -= 360.0;
+    }
+
+    // Check to see that answer is reasonable - ie no more than 1/2 cell away
+    if (rlon - reference.lon).abs() > dlon / 2.0 {
+        return Err(CprError::BadData);
+    }
+
+    Ok(Position::new(rlat, rlon))
+}
+
+/// Receiver location and range, for decoding and checking positions
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Receiver {
+    pub location: Option<Position>,
+    /// Maximum range in meters, 0 for no limit
+    pub max_range: f64,
+}
+
+impl Receiver {
+    #[must_use]
+    pub fn new(location: Option<Position>, max_range: f64) -> Self {
+        Self { location, max_range }
+    }
+
+    /// Globally decode an even/odd pair, `use_odd` selecting the most recent frame
+    ///
+    /// Surface positions need a receiver location to pick between the possible results.
+    pub fn decode_global(
+        &self,
+        even: &CprFrame,
+        odd: &CprFrame,
+        use_odd: bool,
+        surface: bool,
+    ) -> Result<Position, CprError> {
+        let position = if surface {
+            let location = self.location.as_ref().ok_or(CprError::NoReference)?;
+            decode_cpr_surface(location, even, odd, use_odd)?
+        } else {
+            decode_cpr_airborne(even, odd, use_odd)?
+        };
+        self.check_range(position)
+    }
+
+    /// Locally decode a single frame relative to `reference`, the last known position of the
+    /// aircraft, or relative to the receiver when `None`
+    ///
+    /// Decoding relative to the receiver is only unambiguous with a maximum range of at most
+    /// 180NM for airborne and 45NM for surface positions.
+    pub fn decode_local(
+        &self,
+        reference: Option<&Position>,
+        frame: &CprFrame,
+        surface: bool,
+    ) -> Result<Position, CprError> {
+        let reference = match reference {
+            Some(reference) => reference,
+            None => {
+                let limit =
+                    if surface { LOCAL_SURFACE_MAX_RANGE } else { LOCAL_AIRBORNE_MAX_RANGE };
+                if self.max_range == 0.0 || self.max_range > limit {
+                    return Err(CprError::NoReference);
+                }
+                self.location.as_ref().ok_or(CprError::NoReference)?
+            }
+        };
+        let position = decode_cpr_relative(reference, frame, surface)?;
+        self.check_range(position)
+    }
+
+    /// Reject positions further from the receiver than `max_range`
+    pub fn check_range(&self, position: Position) -> Result<Position, CprError> {
+        match self.location {
+            Some(location)
+                if self.max_range > 0.0 && location.distance(&position) > self.max_range =>
+            {
+                Err(CprError::OutOfRange)
+            }
+            _ => Ok(position),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EVEN: CprFrame = CprFrame { odd: false, lat: 93000, lon: 51372 };
+    const ODD: CprFrame = CprFrame { odd: true, lat: 74158, lon: 50194 };
+
+    /// Encode a position, the inverse of the decoding functions
+    fn encode(position: &Position, odd: bool, surface: bool) -> CprFrame {
+        let scale = if surface { 90.0 } else { 360.0 };
+        let dlat = scale / if odd { 59.0 } else { 60.0 };
+        let yz = (CPR_MAX * cpr_mod_double(position.lat, dlat) / dlat + 0.5).floor();
+        let rlat = dlat * (yz / CPR_MAX + (position.lat / dlat).floor());
+        let dlon = cpr_dlon_function(rlat, odd, surface);
+        let xz = (CPR_MAX * cpr_mod_double(position.lon, dlon) / dlon + 0.5).floor();
+        CprFrame { odd, lat: yz as u32 % (1 << 17), lon: xz as u32 % (1 << 17) }
+    }
+
+    fn assert_near(a: &Position, b: &Position) {
+        assert!((a.lat - b.lat).abs() < 0.0001, "{a:?} != {b:?}");
+        assert!((a.lon - b.lon).abs() < 0.0001, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn nl() {
+        assert_eq!(cpr_nl_function(0.0), 59);
+        assert_eq!(cpr_nl_function(10.47), 59);
+        assert_eq!(cpr_nl_function(10.48), 58);
+        assert_eq!(cpr_nl_function(-52.2), 36);
+        assert_eq!(cpr_nl_function(86.9), 2);
+        assert_eq!(cpr_nl_function(88.0), 1);
+    }
+
+    #[test]
+    fn airborne_global() {
+        // 8d40621d58c382d690c8ac2863a7 and 8d40621d58c386435cc412692ad6
+        let position = decode_cpr_airborne(&EVEN, &ODD, false).unwrap();
+        assert_near(&position, &Position::new(52.25720, 3.91937));
+
+        let position = decode_cpr_airborne(&EVEN, &ODD, true).unwrap();
+        assert_near(&position, &Position::new(52.26578, 3.93891));
+    }
+
+    #[test]
+    fn airborne_local() {
+        let reference = Position::new(52.258, 3.918);
+        let position = decode_cpr_relative(&reference, &EVEN, false).unwrap();
+        assert_near(&position, &Position::new(52.25720, 3.91937));
+
+        // a reference further than half a zone away aliases into the next zone
+        let reference = Position::new(48.0, 3.918);
+        let position = decode_cpr_relative(&reference, &EVEN, false).unwrap();
+        assert!((position.lat - 46.25720).abs() < 0.0001);
+    }
+
+    #[test]
+    fn surface() {
+        let receiver = Receiver::new(Some(Position::new(-33.9, 151.1)), 0.0);
+        let expected = Position::new(-33.94612, 151.17731);
+        let even = encode(&expected, false, true);
+        let odd = encode(&expected, true, true);
+
+        let position = receiver.decode_global(&even, &odd, true, true).unwrap();
+        assert_near(&position, &expected);
+
+        let position = decode_cpr_relative(&receiver.location.unwrap(), &odd, true).unwrap();
+        assert_near(&position, &expected);
+
+        // surface decoding needs a reference
+        let receiver = Receiver::default();
+        assert_eq!(receiver.decode_global(&even, &odd, true, true), Err(CprError::NoReference));
+    }
+
+    #[test]
+    fn receiver_range() {
+        let receiver = Receiver::new(Some(Position::new(52.3, 4.7)), 100_000.0);
+        let position = receiver.decode_global(&EVEN, &ODD, false, false).unwrap();
+        assert_near(&position, &Position::new(52.25720, 3.91937));
+        let position = receiver.decode_local(None, &EVEN, false).unwrap();
+        assert_near(&position, &Position::new(52.25720, 3.91937));
+
+        let receiver = Receiver::new(Some(Position::new(48.0, 2.0)), 100_000.0);
+        assert_eq!(receiver.decode_global(&EVEN, &ODD, false, false), Err(CprError::OutOfRange));
+
+        // without a range limit, relative decoding to the receiver is ambiguous
+        let receiver = Receiver::new(Some(Position::new(52.3, 4.7)), 0.0);
+        assert_eq!(receiver.decode_local(None, &EVEN, false), Err(CprError::NoReference));
+    }
+}
@@ -1,4 +1,9 @@
-//This module includes functionality translated from mode_s.c
+//This module includes functionality translated from mode_s.c and crc.c
+
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+use crate::{MODES_LONG_MSG_BYTES, MODES_SHORT_MSG_BYTES};
 
 pub const CRC_TABLE: [u32; 256] = [
     0x0000_0000,
@@ -280,3 +285,138 @@ pub fn modes_checksum(message: &[u8], bits: usize) -> u32 {
 
     rem
 }
+
+/// Bit errors matching a syndrome, as found by [`modes_checksum_diagnose`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ErrorInfo {
+    /// Number of bit errors, 0 to 2
+    pub errors: usize,
+    /// Bit positions of the errors, counted from the first bit of the message
+    pub bit: [u8; 2],
+}
+
+/// Syndromes of all correctable 1 and 2 bit errors for one message length
+struct SyndromeTable {
+    one_bit: HashMap<u32, ErrorInfo>,
+    two_bit: HashMap<u32, ErrorInfo>,
+}
+
+impl SyndromeTable {
+    // crc.c prepareErrorTable
+    fn new(bits: usize) -> Self {
+        // syndrome of each single bit error, using the CRC_TABLE based checksum
+        let single: Vec<u32> = (0..bits)
+            .map(|i| {
+                let mut msg = [0_u8; MODES_LONG_MSG_BYTES];
+                msg[i / 8] = 1 << (7 - (i % 8));
+                modes_checksum(&msg, bits)
+            })
+            .collect();
+
+        // ignore the first 5 bits, the DF type
+        let mut one_bit = HashMap::new();
+        for (i, syndrome) in single.iter().enumerate().skip(5) {
+            one_bit.insert(*syndrome, ErrorInfo { errors: 1, bit: [i as u8, 0] });
+        }
+
+        // two bit errors that are ambiguous, or look like a single bit error, are not corrected
+        let mut two_bit = HashMap::new();
+        let mut ambiguous = HashSet::new();
+        for i in 5..bits {
+            for j in (i + 1)..bits {
+                let syndrome = single[i] ^ single[j];
+                if one_bit.contains_key(&syndrome) || ambiguous.contains(&syndrome) {
+                    continue;
+                }
+                if two_bit
+                    .insert(syndrome, ErrorInfo { errors: 2, bit: [i as u8, j as u8] })
+                    .is_some()
+                {
+                    two_bit.remove(&syndrome);
+                    ambiguous.insert(syndrome);
+                }
+            }
+        }
+
+        Self { one_bit, two_bit }
+    }
+}
+
+static SYNDROME_TABLE_SHORT: OnceLock<SyndromeTable> = OnceLock::new();
+static SYNDROME_TABLE_LONG: OnceLock<SyndromeTable> = OnceLock::new();
+
+// crc.c modesChecksumDiagnose
+/// Find the bit errors of a `bits` long message with CRC `syndrome`, fixing at most
+/// `fix_errors` bits
+#[must_use]
+pub fn modes_checksum_diagnose(syndrome: u32, bits: usize, fix_errors: usize) -> Option<ErrorInfo> {
+    if syndrome == 0 {
+        return Some(ErrorInfo { errors: 0, bit: [0, 0] });
+    }
+
+    if fix_errors == 0 {
+        return None;
+    }
+
+    let table = if bits == MODES_SHORT_MSG_BYTES * 8 {
+        SYNDROME_TABLE_SHORT.get_or_init(|| SyndromeTable::new(MODES_SHORT_MSG_BYTES * 8))
+    } else {
+        SYNDROME_TABLE_LONG.get_or_init(|| SyndromeTable::new(MODES_LONG_MSG_BYTES * 8))
+    };
+
+    if let Some(info) = table.one_bit.get(&syndrome) {
+        return Some(*info);
+    }
+    if fix_errors >= 2 {
+        return table.two_bit.get(&syndrome).copied();
+    }
+    None
+}
+
+// crc.c modesChecksumFix
+/// Flip the bits found by [`modes_checksum_diagnose`]
+pub fn modes_checksum_fix(msg: &mut [u8], info: &ErrorInfo) {
+    for bit in &info.bit[..info.errors] {
+        let bit = usize::from(*bit);
+        msg[bit / 8] ^= 1 << (7 - (bit % 8));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hexlit::hex;
+
+    #[test]
+    fn diagnose() {
+        let valid = hex!("8dad929358b9c6273f002169c02e");
+        assert_eq!(modes_checksum(&valid, 112), 0);
+
+        // single bit error
+        let mut msg = valid;
+        msg[5] ^= 0x10;
+        let syndrome = modes_checksum(&msg, 112);
+        assert_eq!(modes_checksum_diagnose(syndrome, 112, 0), None);
+        let info = modes_checksum_diagnose(syndrome, 112, 1).unwrap();
+        assert_eq!(info, ErrorInfo { errors: 1, bit: [43, 0] });
+        modes_checksum_fix(&mut msg, &info);
+        assert_eq!(msg, valid);
+
+        // two bit error
+        let mut msg = valid;
+        msg[2] ^= 0x01;
+        msg[12] ^= 0x80;
+        let syndrome = modes_checksum(&msg, 112);
+        assert_eq!(modes_checksum_diagnose(syndrome, 112, 1), None);
+        let info = modes_checksum_diagnose(syndrome, 112, 2).unwrap();
+        assert_eq!(info, ErrorInfo { errors: 2, bit: [23, 96] });
+        modes_checksum_fix(&mut msg, &info);
+        assert_eq!(msg, valid);
+
+        // the DF field is never corrected
+        let mut msg = valid;
+        msg[0] ^= 0x80;
+        let syndrome = modes_checksum(&msg, 112);
+        assert_eq!(modes_checksum_diagnose(syndrome, 112, 2), None);
+    }
+}
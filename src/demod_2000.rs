@@ -0,0 +1,211 @@
+// This module includes functionality translated from demod_2000.c
+
+use crate::{
+    Demodulator, Error, MODES_LONG_MSG_BYTES, MagnitudeBuffer,
+    crc::modes_checksum_fix,
+    demod_2400::{ModeSMessage, MsgLen},
+    mode_s::score_modes_message,
+};
+
+/// Samples of the preamble, 8us at 2MHz
+pub(crate) const MODES_PREAMBLE_SAMPLES: usize = 16;
+/// Samples of a long message, 2 per bit
+const MODES_LONG_MSG_SAMPLES: usize = MODES_LONG_MSG_BYTES * 8 * 2;
+/// Bits of the first 56 used for the signal and noise levels
+const MODES_SHORT_MSG_BITS: usize = 56;
+/// Bits with equal halves allowed before giving up on a candidate
+const MODES_MSG_ENCODER_ERRS: usize = 3;
+
+impl Demodulator {
+    // demod_2000.c demodulate2000
+    pub fn demodulate2000(&mut self, mag: &MagnitudeBuffer) -> Result<Vec<ModeSMessage>, Error> {
+        self.expire_icao_filter(mag.sys_timestamp);
+        let mut results = vec![];
+
+        let offset = mag.trailing_start();
+        let data = &mag.data[offset..];
+        // payload, with one extra sample read by the phase correction
+        let mut payload = [0_u16; MODES_LONG_MSG_SAMPLES + 1];
+
+        let mut skip_count: usize = 0;
+        'jloop: for j in 0..mag.length {
+            if skip_count > 0 {
+                skip_count -= 1;
+                continue 'jloop;
+            }
+
+            let Some(out_of_phase) = check_preamble(data, j) else {
+                continue 'jloop;
+            };
+
+            let (timestamp_12mhz, sys_timestamp) = mag.timestamps_at(offset + j);
+            let mut bestmsg = ModeSMessage {
+                msg: [0_u8; MODES_LONG_MSG_BYTES],
+                signal_level: 0.,
+                score: -2,
+                phase: None,
+                sample_offset: mag.sample_offset_at(offset + j),
+                msglen: MsgLen::Short,
+                timestamp_12mhz,
+                sys_timestamp,
+                corrected: None,
+            };
+
+            let start = j + MODES_PREAMBLE_SAMPLES;
+            payload.copy_from_slice(&data[start..start + MODES_LONG_MSG_SAMPLES + 1]);
+
+            // try as is, then with phase correction if the preamble looks out of phase
+            for use_correction in [false, true] {
+                if use_correction {
+                    if !out_of_phase {
+                        break;
+                    }
+                    apply_phase_correction(&mut payload);
+                }
+
+                let Some(msg) = decode_bits(&payload) else {
+                    continue;
+                };
+
+                if let Some((msglen, score, corrected)) =
+                    score_modes_message(&msg, &mut self.icao_filter, self.config.fix_errors)
+                    && score > bestmsg.score
+                {
+                    bestmsg.msglen = msglen;
+                    bestmsg.msg.clone_from_slice(&msg);
+                    bestmsg.score = score;
+                    bestmsg.corrected = corrected;
+
+                    let mut scaled_signal_power = 0_u64;
+                    let signal_len = msg_bits(&bestmsg.msglen) * 2;
+                    for k in 0..signal_len {
+                        let mag = data[start + k] as u64;
+                        scaled_signal_power += mag * mag;
+                    }
+                    let signal_power = scaled_signal_power as f64 / 65535.0 / 65535.0;
+                    bestmsg.signal_level = signal_power / signal_len as f64;
+                }
+            }
+
+            // Do we have a candidate?
+            if bestmsg.score < 0 {
+                continue 'jloop;
+            }
+
+            if let Some(info) = &bestmsg.corrected {
+                modes_checksum_fix(&mut bestmsg.msg, info);
+            }
+
+            // skip the rest of the message, it can't hold another preamble
+            skip_count = MODES_PREAMBLE_SAMPLES + msg_bits(&bestmsg.msglen) * 2 - 1;
+            results.push(bestmsg);
+        }
+
+        Ok(results)
+    }
+}
+
+#[inline(always)]
+pub(crate) fn msg_bits(msglen: &MsgLen) -> usize {
+    match msglen {
+        MsgLen::Short => MODES_SHORT_MSG_BITS,
+        MsgLen::Long => MODES_LONG_MSG_BYTES * 8,
+    }
+}
+
+/// Check for a preamble starting at `data[j]`, returning whether it looks out of phase
+#[inline(always)]
+fn check_preamble(data: &[u16], j: usize) -> Option<bool> {
+    let m = &data[j..j + MODES_PREAMBLE_SAMPLES];
+
+    // pulses at 0,2,7,9, each 0.5us, with the other samples lower
+    if !(m[0] > m[1]
+        && m[1] < m[2]
+        && m[2] > m[3]
+        && m[3] < m[0]
+        && m[4] < m[0]
+        && m[5] < m[0]
+        && m[6] < m[0]
+        && m[7] > m[8]
+        && m[8] < m[9]
+        && m[9] > m[6])
+    {
+        return None;
+    }
+
+    // the samples between the pulses and before the data must be quiet
+    let high = (u32::from(m[0]) + u32::from(m[2]) + u32::from(m[7]) + u32::from(m[9])) / 6;
+    if [m[4], m[5], m[11], m[12], m[13], m[14]].iter().any(|&s| u32::from(s) >= high) {
+        return None;
+    }
+
+    // demod_2000.c detectOutOfPhase
+    let before = if j > 0 { data[j - 1] } else { 0 };
+    Some(m[3] > m[2] / 3 || m[10] > m[9] / 3 || m[6] > m[7] / 3 || before > m[1] / 3)
+}
+
+// demod_2000.c applyPhaseCorrection
+/// Move energy leaking into the next bit back, for a message sampled out of phase
+#[inline(always)]
+fn apply_phase_correction(payload: &mut [u16]) {
+    for j in (0..MODES_LONG_MSG_SAMPLES).step_by(2) {
+        payload[j + 2] = if payload[j] > payload[j + 1] {
+            // One
+            (u32::from(payload[j + 2]) * 5 / 4).min(u32::from(u16::MAX)) as u16
+        } else {
+            // Zero
+            (u32::from(payload[j + 2]) * 4 / 5) as u16
+        };
+    }
+}
+
+/// Slice the bits from pairs of samples, `None` for too many bits with equal halves or too weak
+/// a signal
+#[inline(always)]
+pub(crate) fn decode_bits<T: Copy + Ord + Into<u32>>(
+    payload: &[T],
+) -> Option<[u8; MODES_LONG_MSG_BYTES]> {
+    let mut msg = [0_u8; MODES_LONG_MSG_BYTES];
+    let mut bits = MODES_LONG_MSG_BYTES * 8;
+    let mut errors = 0;
+    let mut signal = 0_u32;
+    let mut noise = 0_u32;
+    let mut last_bit = 0;
+
+    let mut i = 0;
+    while i < bits {
+        let a = payload[i * 2];
+        let b = payload[i * 2 + 1];
+        let bit = if a > b {
+            1
+        } else if a < b {
+            0
+        } else {
+            // no way to tell, keep the previous bit
+            errors += 1;
+            last_bit
+        };
+        if i < MODES_SHORT_MSG_BITS {
+            signal += a.max(b).into();
+            noise += a.min(b).into();
+        }
+        msg[i / 8] |= bit << (7 - i % 8);
+        last_bit = bit;
+
+        // the downlink format decides the length of the message
+        if i == 7 && msg[0] >> 3 < 16 {
+            bits = MODES_SHORT_MSG_BITS;
+        }
+        if errors > MODES_MSG_ENCODER_ERRS {
+            return None;
+        }
+        i += 1;
+    }
+
+    // about 3.5dB SNR
+    if signal * 2 < noise * 3 {
+        return None;
+    }
+
+    Some(msg)
+}
@@ -1,7 +1,11 @@
 // This module includes functionality translated from demod_2400.c
 
+use std::time::SystemTime;
+
 use crate::{
-    MODES_LONG_MSG_BYTES, MODES_SHORT_MSG_BYTES, MagnitudeBuffer, mode_s::score_modes_message,
+    Demodulator, Error, MODES_LONG_MSG_BYTES, MODES_SHORT_MSG_BYTES, MagnitudeBuffer,
+    crc::{ErrorInfo, modes_checksum_fix},
+    mode_s::score_modes_message,
 };
 
 #[derive(Clone, Copy, Debug)]
@@ -84,6 +88,7 @@ impl Phase {
 }
 
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum MsgLen {
     Short,
     Long,
@@ -92,13 +97,23 @@ pub enum MsgLen {
 #[derive(Debug)]
 pub struct ModeSMessage {
     /// Type of message
-    msglen: MsgLen,
+    pub(crate) msglen: MsgLen,
     /// Binary message
-    msg: [u8; MODES_LONG_MSG_BYTES],
+    pub(crate) msg: [u8; MODES_LONG_MSG_BYTES],
     ///  RSSI, in the range [0..1], as a fraction of full-scale power
-    signal_level: f64,
+    pub(crate) signal_level: f64,
     /// Scoring from scoreModesMessage, if used
-    score: i32,
+    pub(crate) score: i32,
+    /// Phase that decoded the message, `try_phase` of demodulate2400
+    pub(crate) phase: Option<u8>,
+    /// Count of samples received before the start of the preamble
+    pub(crate) sample_offset: u64,
+    /// 12MHz clock tick of the start of the preamble
+    pub(crate) timestamp_12mhz: u64,
+    /// Wall clock time of the start of the preamble
+    pub(crate) sys_timestamp: SystemTime,
+    /// Bit errors fixed by CRC error correction
+    pub(crate) corrected: Option<ErrorInfo>,
 }
 
 impl ModeSMessage {
@@ -109,106 +124,203 @@ impl ModeSMessage {
             MsgLen::Long => &self.msg[..MODES_LONG_MSG_BYTES],
         }
     }
-}
 
-#[inline(always)]
-pub fn demodulate2400(mag: &MagnitudeBuffer) -> Result<Vec<ModeSMessage>, &'static str> {
-    let mut results = vec![];
+    /// 12MHz clock tick of the start of the preamble, for MLAT
+    #[inline(always)]
+    pub fn timestamp_12mhz(&self) -> u64 {
+        self.timestamp_12mhz
+    }
 
-    let data = &mag.data;
+    /// Wall clock time of the start of the preamble
+    #[inline(always)]
+    pub fn sys_timestamp(&self) -> SystemTime {
+        self.sys_timestamp
+    }
 
-    let mut skip_count: usize = 0;
-    'jloop: for j in 0..mag.length {
-        if skip_count > 0 {
-            skip_count -= 1;
-            continue 'jloop;
+    /// Positions of the bits fixed by CRC error correction, counted from the first bit of the
+    /// message
+    #[inline(always)]
+    pub fn corrected_bits(&self) -> &[u8] {
+        match &self.corrected {
+            Some(info) => &info.bit[..info.errors],
+            None => &[],
         }
+    }
 
-        if let Some((high, base_signal, base_noise)) = check_preamble(&data[j..j + 14]) {
-            // Check for enough signal
-            if base_signal * 2 < 3 * base_noise {
-                // about 3.5dB SNR
-                continue 'jloop;
-            }
+    /// Length of the message, from the downlink format
+    #[inline(always)]
+    pub fn msglen(&self) -> &MsgLen {
+        &self.msglen
+    }
+
+    /// Mean power of the message samples, as a fraction of full-scale power
+    #[inline(always)]
+    pub fn signal_level(&self) -> f64 {
+        self.signal_level
+    }
+
+    /// [`Self::signal_level`] in dBFS, 0 at full scale
+    #[inline(always)]
+    pub fn rssi_dbfs(&self) -> f64 {
+        10.0 * self.signal_level.log10()
+    }
+
+    /// Score of the message from scoreModesMessage, higher for messages more likely to be valid
+    #[inline(always)]
+    pub fn score(&self) -> i32 {
+        self.score
+    }
+
+    /// Phase of the 2.4MHz samples that decoded the message, in fifths of a sample from 4 to 8 as
+    /// `try_phase` of demod_2400.c. `None` for messages from the other sample rates
+    #[inline(always)]
+    pub fn phase(&self) -> Option<u8> {
+        self.phase
+    }
+
+    /// Count of samples received before the start of the preamble, as counted by
+    /// [`MagnitudeBuffer::set_timestamps`]
+    #[inline(always)]
+    pub fn sample_offset(&self) -> u64 {
+        self.sample_offset
+    }
+}
 
-            // Check that the "quiet" bits 6,7,15,16,17 are actually quiet
-            if i32::from(data[j + 5]) >= high
-                || i32::from(data[j + 6]) >= high
-                || i32::from(data[j + 7]) >= high
-                || i32::from(data[j + 8]) >= high
-                || i32::from(data[j + 14]) >= high
-                || i32::from(data[j + 15]) >= high
-                || i32::from(data[j + 16]) >= high
-                || i32::from(data[j + 17]) >= high
-                || i32::from(data[j + 18]) >= high
-            {
+#[cfg(feature = "serde")]
+impl serde::Serialize for ModeSMessage {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut s = serializer.serialize_struct("ModeSMessage", 10)?;
+        s.serialize_field("msg", self.buffer())?;
+        s.serialize_field("msglen", &self.msglen)?;
+        s.serialize_field("signal_level", &self.signal_level)?;
+        s.serialize_field("rssi_dbfs", &self.rssi_dbfs())?;
+        s.serialize_field("score", &self.score)?;
+        s.serialize_field("phase", &self.phase)?;
+        s.serialize_field("sample_offset", &self.sample_offset)?;
+        s.serialize_field("timestamp_12mhz", &self.timestamp_12mhz)?;
+        s.serialize_field("sys_timestamp", &self.sys_timestamp)?;
+        s.serialize_field("corrected_bits", self.corrected_bits())?;
+        s.end()
+    }
+}
+
+impl Demodulator {
+    #[inline(always)]
+    pub fn demodulate2400(&mut self, mag: &MagnitudeBuffer) -> Result<Vec<ModeSMessage>, Error> {
+        self.expire_icao_filter(mag.sys_timestamp);
+        let mut results = vec![];
+
+        let offset = mag.trailing_start();
+        let data = &mag.data[offset..];
+
+        let mut skip_count: usize = 0;
+        'jloop: for j in 0..mag.length {
+            if skip_count > 0 {
+                skip_count -= 1;
                 continue 'jloop;
             }
 
-            // Try all phases
-            let mut bestmsg = ModeSMessage {
-                msg: [0_u8; MODES_LONG_MSG_BYTES],
-                signal_level: 0.,
-                score: -2,
-                msglen: MsgLen::Short,
-            };
-
-            let mut msg: [u8; MODES_LONG_MSG_BYTES] = [0_u8; MODES_LONG_MSG_BYTES];
-
-            for try_phase in 4..9 {
-                let mut slice_loc: usize = j + 19 + (try_phase / 5);
-                let mut phase = Phase::from(try_phase);
-
-                for msg in msg.iter_mut().take(MODES_LONG_MSG_BYTES) {
-                    let slice_this_byte: &[u16] = &data[slice_loc..];
-
-                    let starting_phase = phase;
-                    let mut the_byte = 0x00;
-                    let mut index = 0;
-                    // for each phase-bit
-                    for i in 0..8 {
-                        // find if phase distance denotes a high bit
-                        if phase.calculate_bit(&slice_this_byte[index..index + 4]) > 0 {
-                            the_byte |= 1 << (7 - i);
-                        }
-                        // increment to next phase, increase index
-                        index = phase.increment_index(index);
-                        phase = phase.next();
-                    }
-                    // save bytes and move the next starting phase
-                    *msg = the_byte;
-                    slice_loc += index;
-                    phase = starting_phase.next_start();
+            if let Some((high, base_signal, base_noise)) = check_preamble(&data[j..j + 14]) {
+                // Check for enough signal
+                if base_signal * 2 < 3 * base_noise {
+                    // about 3.5dB SNR
+                    continue 'jloop;
                 }
 
-                if let Some((msglen, score)) = score_modes_message(&msg)
-                    && score > bestmsg.score
+                // Check that the "quiet" bits 6,7,15,16,17 are actually quiet
+                if i32::from(data[j + 5]) >= high
+                    || i32::from(data[j + 6]) >= high
+                    || i32::from(data[j + 7]) >= high
+                    || i32::from(data[j + 8]) >= high
+                    || i32::from(data[j + 14]) >= high
+                    || i32::from(data[j + 15]) >= high
+                    || i32::from(data[j + 16]) >= high
+                    || i32::from(data[j + 17]) >= high
+                    || i32::from(data[j + 18]) >= high
                 {
-                    bestmsg.msglen = msglen;
-                    bestmsg.msg.clone_from_slice(&msg);
-                    bestmsg.score = score;
-
-                    let mut scaled_signal_power = 0_u64;
-                    let signal_len = msg.len() * 12 / 5;
-                    for k in 0..signal_len {
-                        let mag = data[j + 19 + k] as u64;
-                        scaled_signal_power += mag * mag;
+                    continue 'jloop;
+                }
+
+                // Try all phases
+                let (timestamp_12mhz, sys_timestamp) = mag.timestamps_at(offset + j);
+                let mut bestmsg = ModeSMessage {
+                    msg: [0_u8; MODES_LONG_MSG_BYTES],
+                    signal_level: 0.,
+                    score: -2,
+                    phase: None,
+                    sample_offset: mag.sample_offset_at(offset + j),
+                    msglen: MsgLen::Short,
+                    timestamp_12mhz,
+                    sys_timestamp,
+                    corrected: None,
+                };
+
+                let mut msg: [u8; MODES_LONG_MSG_BYTES] = [0_u8; MODES_LONG_MSG_BYTES];
+
+                for try_phase in 4..9 {
+                    let mut slice_loc: usize = j + 19 + (try_phase / 5);
+                    let mut phase = Phase::from(try_phase);
+
+                    for msg in msg.iter_mut().take(MODES_LONG_MSG_BYTES) {
+                        let slice_this_byte: &[u16] = &data[slice_loc..];
+
+                        let starting_phase = phase;
+                        let mut the_byte = 0x00;
+                        let mut index = 0;
+                        // for each phase-bit
+                        for i in 0..8 {
+                            // find if phase distance denotes a high bit
+                            if phase.calculate_bit(&slice_this_byte[index..index + 4]) > 0 {
+                                the_byte |= 1 << (7 - i);
+                            }
+                            // increment to next phase, increase index
+                            index = phase.increment_index(index);
+                            phase = phase.next();
+                        }
+                        // save bytes and move the next starting phase
+                        *msg = the_byte;
+                        slice_loc += index;
+                        phase = starting_phase.next_start();
+                    }
+
+                    if let Some((msglen, score, corrected)) =
+                        score_modes_message(&msg, &mut self.icao_filter, self.config.fix_errors)
+                        && score > bestmsg.score
+                    {
+                        bestmsg.msglen = msglen;
+                        bestmsg.msg.clone_from_slice(&msg);
+                        bestmsg.score = score;
+                        bestmsg.phase = Some(try_phase as u8);
+                        bestmsg.corrected = corrected;
+
+                        let mut scaled_signal_power = 0_u64;
+                        let signal_len = msg.len() * 12 / 5;
+                        for k in 0..signal_len {
+                            let mag = data[j + 19 + k] as u64;
+                            scaled_signal_power += mag * mag;
+                        }
+                        let signal_power = scaled_signal_power as f64 / 65535.0 / 65535.0;
+                        bestmsg.signal_level = signal_power / signal_len as f64;
                     }
-                    let signal_power = scaled_signal_power as f64 / 65535.0 / 65535.0;
-                    bestmsg.signal_level = signal_power / signal_len as f64;
                 }
-            }
 
-            // Do we have a candidate?
-            if bestmsg.score < 0 {
-                continue 'jloop;
-            }
+                // Do we have a candidate?
+                if bestmsg.score < 0 {
+                    continue 'jloop;
+                }
+
+                if let Some(info) = &bestmsg.corrected {
+                    modes_checksum_fix(&mut bestmsg.msg, info);
+                }
 
-            results.push(bestmsg);
+                results.push(bestmsg);
+            }
         }
-    }
 
-    Ok(results)
+        Ok(results)
+    }
 }
 
 #[inline(always)]
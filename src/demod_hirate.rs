@@ -0,0 +1,166 @@
+// This module includes functionality adapted from demod_2000.c, for sample rates with several
+// samples per half bit
+
+use crate::{
+    Demodulator, Error, MODES_LONG_MSG_BYTES, MagnitudeBuffer, TRAILING_SAMPLES,
+    crc::modes_checksum_fix,
+    demod_2000::{MODES_PREAMBLE_SAMPLES, decode_bits, msg_bits},
+    demod_2400::ModeSMessage,
+    mode_s::score_modes_message,
+};
+
+/// Half bits of 0.5us in the preamble
+const PREAMBLE_HALF_BITS: usize = MODES_PREAMBLE_SAMPLES;
+/// Half bits of 0.5us in a long message
+const LONG_MSG_HALF_BITS: usize = MODES_LONG_MSG_BYTES * 8 * 2;
+
+impl Demodulator {
+    /// Demodulate samples at 6MHz, 8MHz or 12MHz.
+    ///
+    /// Each half bit is the sum of the samples within it, and the preamble is aligned to the
+    /// sample giving the strongest preamble, so the timestamp of a message is accurate to about
+    /// one sample.
+    pub fn demodulate_hirate(&mut self, mag: &MagnitudeBuffer) -> Result<Vec<ModeSMessage>, Error> {
+        let n = mag.sample_rate.half_bit_samples();
+        if n < 2 {
+            return Err(Error::UnsupportedFormat(format!(
+                "sample rate {}Hz for demodulate_hirate",
+                mag.sample_rate.hz()
+            )));
+        }
+        self.expire_icao_filter(mag.sys_timestamp);
+
+        let mut results = vec![];
+
+        // sums of all samples before each index, for the sum of any half bit
+        let offset = mag.trailing_start();
+        let data = &mag.data[offset..TRAILING_SAMPLES + mag.length];
+        let mut prefix = Vec::with_capacity(data.len() + 1);
+        let mut sum = 0_u64;
+        prefix.push(sum);
+        for &m in data {
+            sum += u64::from(m);
+            prefix.push(sum);
+        }
+        let half_bit = |start: usize, k: usize| -> u32 {
+            (prefix[start + (k + 1) * n] - prefix[start + k * n]) as u32
+        };
+
+        let mut payload = [0_u32; LONG_MSG_HALF_BITS];
+
+        let mut skip_count: usize = 0;
+        'jloop: for j in 0..mag.length {
+            if skip_count > 0 {
+                skip_count -= 1;
+                continue 'jloop;
+            }
+
+            let Some(mut strength) = check_preamble(|k| half_bit(j, k)) else {
+                continue 'jloop;
+            };
+            // the preamble passes up to a half bit early, move on while it gets stronger
+            let mut start = j;
+            while start < mag.length
+                && let Some(next) = check_preamble(|k| half_bit(start + 1, k))
+                && next > strength
+            {
+                start += 1;
+                strength = next;
+            }
+            // the starts up to the strongest are the same preamble
+            skip_count = start - j;
+            // leave preambles starting in the trailing samples to the next buffer
+            if start >= mag.length {
+                continue 'jloop;
+            }
+
+            let payload_start = start + PREAMBLE_HALF_BITS * n;
+            for (k, h) in payload.iter_mut().enumerate() {
+                *h = half_bit(payload_start, k);
+            }
+            let Some(msg) = decode_bits(&payload) else {
+                continue 'jloop;
+            };
+            let Some((msglen, score, corrected)) =
+                score_modes_message(&msg, &mut self.icao_filter, self.config.fix_errors)
+            else {
+                continue 'jloop;
+            };
+            if score < 0 {
+                continue 'jloop;
+            }
+
+            let (timestamp_12mhz, sys_timestamp) = mag.timestamps_at(offset + start);
+            let signal_len = msg_bits(&msglen) * 2 * n;
+            let mut scaled_signal_power = 0_u64;
+            for &m in &data[payload_start..payload_start + signal_len] {
+                scaled_signal_power += u64::from(m) * u64::from(m);
+            }
+            let signal_power = scaled_signal_power as f64 / 65535.0 / 65535.0;
+
+            let mut bestmsg = ModeSMessage {
+                msg,
+                signal_level: signal_power / signal_len as f64,
+                score,
+                phase: None,
+                sample_offset: mag.sample_offset_at(offset + start),
+                msglen,
+                timestamp_12mhz,
+                sys_timestamp,
+                corrected,
+            };
+            if let Some(info) = &bestmsg.corrected {
+                modes_checksum_fix(&mut bestmsg.msg, info);
+            }
+
+            // skip the rest of the message, it can't hold another preamble
+            skip_count += (PREAMBLE_HALF_BITS + msg_bits(&bestmsg.msglen) * 2) * n - 1;
+            results.push(bestmsg);
+        }
+
+        Ok(results)
+    }
+}
+
+/// Check for a preamble in the sums of the 16 half bits given by `half_bit`, returning its
+/// strength
+#[inline(always)]
+fn check_preamble(half_bit: impl Fn(usize) -> u32) -> Option<i64> {
+    // pulses at 0,2,7,9, with the other half bits lower, summed lazily as most starts fail early
+    let h0 = half_bit(0);
+    let h1 = half_bit(1);
+    if h0 <= h1 {
+        return None;
+    }
+    let h2 = half_bit(2);
+    let h3 = half_bit(3);
+    if !(h1 < h2 && h2 > h3 && h3 < h0) {
+        return None;
+    }
+    let h = [
+        h0,
+        h1,
+        h2,
+        h3,
+        half_bit(4),
+        half_bit(5),
+        half_bit(6),
+        half_bit(7),
+        half_bit(8),
+        half_bit(9),
+    ];
+    if !(h[4] < h[0] && h[5] < h[0] && h[6] < h[0] && h[7] > h[8] && h[8] < h[9] && h[9] > h[6]) {
+        return None;
+    }
+
+    // the half bits between the pulses and before the data must be quiet
+    let high = (h[0] + h[2] + h[7] + h[9]) / 6;
+    if h[4] >= high || h[5] >= high || (11..15).any(|k| half_bit(k) >= high) {
+        return None;
+    }
+
+    let pulses = i64::from(h[0] + h[2] + h[7] + h[9]);
+    let quiet = i64::from(h[1] + h[3] + h[4] + h[5] + h[6] + h[8]);
+    // 4 pulses against 6 quiet half bits
+    Some(pulses * 3 - quiet * 2)
+}
@@ -0,0 +1,59 @@
+use std::fmt;
+use std::io;
+
+/// Errors of the sample readers, converters and demodulators
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing samples failed
+    Io(io::Error),
+    /// Input that doesn't hold whole samples, such as a recording ending within a sample
+    MalformedInput(String),
+    /// More samples than fit in one [`crate::MagnitudeBuffer`]
+    BufferOverflow {
+        /// Number of samples given
+        len: usize,
+        /// Number of samples that fit, `MODES_MAG_BUF_SAMPLES`
+        capacity: usize,
+    },
+    /// Sample format or sample rate without a converter or demodulator
+    UnsupportedFormat(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::MalformedInput(s) => write!(f, "malformed input: {s}"),
+            Self::BufferOverflow { len, capacity } => {
+                write!(f, "{len} samples don't fit in a buffer of {capacity}")
+            }
+            Self::UnsupportedFormat(s) => write!(f, "unsupported {s}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// For callers returning [`io::Result`], errors other than [`Error::Io`] are
+/// [`io::ErrorKind::InvalidData`]
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e,
+            e => Self::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
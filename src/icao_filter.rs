@@ -1,20 +1,12 @@
 //This module includes functionality translated from icao_filter.c
 
-use std::sync::Mutex;
+use std::time::Duration;
 
 const ICAO_FILTER_SIZE: u32 = 4096;
 pub const ICAO_FILTER_ADSB_NT: u32 = 1 << 25;
 
-static ICAO_FILTER_A: Mutex<[u32; 4096]> = Mutex::new([0; 4096]);
-static ICAO_FILTER_B: Mutex<[u32; 4096]> = Mutex::new([0; 4096]);
-
-pub fn icao_flush() {
-    let mut i = ICAO_FILTER_A.lock().unwrap();
-    *i = [0; 4096];
-
-    let mut i = ICAO_FILTER_B.lock().unwrap();
-    *i = [0; 4096];
-}
+/// Default time an address stays in the filter after it was last added, dump1090.h MODES_ICAO_FILTER_TTL
+pub const ICAO_FILTER_TTL: Duration = Duration::from_secs(60);
 
 pub fn icao_hash(a32: u32) -> u32 // icao_filter.c:38
 {
@@ -42,12 +34,61 @@ pub fn icao_hash(a32: u32) -> u32 // icao_filter.c:38
     (hash as u32) & (ICAO_FILTER_SIZE - 1)
 }
 
-// The original function uses a integer return value, but it's used as a boolean
-pub fn icao_filter_add(addr: u32) {
-    let mut h: u32 = icao_hash(addr);
-    let h0: u32 = h;
-    if let Ok(mut icao_filter_a) = ICAO_FILTER_A.lock() {
-        while (icao_filter_a[h as usize] != 0) && (icao_filter_a[h as usize] != addr) {
+/// Recently seen ICAO addresses, used to validate messages with the address in the parity
+///
+/// Addresses are added to table A, and both tables are tested. Every `ttl`, [`Self::expire`]
+/// moves table A to B and clears A, so an address is forgotten between one and two `ttl`s after
+/// it was last added.
+#[derive(Clone, Debug)]
+pub struct IcaoFilter {
+    a: [u32; ICAO_FILTER_SIZE as usize],
+    b: [u32; ICAO_FILTER_SIZE as usize],
+    /// Time between expiries, as 12MHz clock ticks
+    ttl_12mhz: u64,
+    /// 12MHz clock tick of the next expiry
+    next_flip_12mhz: u64,
+}
+
+impl Default for IcaoFilter {
+    fn default() -> Self {
+        Self::new(ICAO_FILTER_TTL)
+    }
+}
+
+impl IcaoFilter {
+    #[must_use]
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            a: [0; ICAO_FILTER_SIZE as usize],
+            b: [0; ICAO_FILTER_SIZE as usize],
+            ttl_12mhz: (ttl.as_nanos() * 12 / 1_000) as u64,
+            next_flip_12mhz: 0,
+        }
+    }
+
+    // icao_filter.c icaoFilterExpire
+    /// Age the filter at the 12MHz clock tick `timestamp_12mhz`
+    ///
+    /// Once `ttl` has passed since the last expiry, table A replaces table B and is cleared.
+    pub fn expire(&mut self, timestamp_12mhz: u64) {
+        if timestamp_12mhz >= self.next_flip_12mhz {
+            self.b = self.a;
+            self.a = [0; ICAO_FILTER_SIZE as usize];
+            self.next_flip_12mhz = timestamp_12mhz + self.ttl_12mhz;
+        }
+    }
+
+    /// Remove all addresses
+    pub fn flush(&mut self) {
+        self.a = [0; ICAO_FILTER_SIZE as usize];
+        self.b = [0; ICAO_FILTER_SIZE as usize];
+    }
+
+    // The original function uses a integer return value, but it's used as a boolean
+    pub fn add(&mut self, addr: u32) {
+        let mut h: u32 = icao_hash(addr);
+        let h0: u32 = h;
+        while (self.a[h as usize] != 0) && (self.a[h as usize] != addr) {
             h = (h + 1) & (ICAO_FILTER_SIZE - 1);
             if h == h0 {
                 eprintln!("icao24 hash table full");
@@ -55,43 +96,81 @@ pub fn icao_filter_add(addr: u32) {
             }
         }
 
-        if icao_filter_a[h as usize] == 0 {
-            icao_filter_a[h as usize] = addr;
+        if self.a[h as usize] == 0 {
+            self.a[h as usize] = addr;
         }
     }
-}
 
-// The original function uses a integer return value, but it's used as a boolean
-pub fn icao_filter_test(addr: u32) -> bool // icao_filter.c:96
-{
-    let mut h: u32 = icao_hash(addr);
-    let h0: u32 = h;
+    // The original function uses a integer return value, but it's used as a boolean
+    #[must_use]
+    pub fn test(&self, addr: u32) -> bool // icao_filter.c:96
+    {
+        let mut h: u32 = icao_hash(addr);
+        let h0: u32 = h;
 
-    if let (Ok(icao_filter_a), Ok(icao_filter_b)) = (ICAO_FILTER_A.lock(), ICAO_FILTER_B.lock()) {
-        'loop_a: while (icao_filter_a[h as usize] != 0) && (icao_filter_a[h as usize] != addr) {
+        'loop_a: while (self.a[h as usize] != 0) && (self.a[h as usize] != addr) {
             h = (h + 1) & (ICAO_FILTER_SIZE - 1);
             if h == h0 {
                 break 'loop_a;
             }
         }
 
-        if icao_filter_a[h as usize] == addr {
+        if self.a[h as usize] == addr {
             return true;
         }
 
         h = h0;
 
-        'loop_b: while (icao_filter_b[h as usize] != 0) && (icao_filter_b[h as usize] != addr) {
+        'loop_b: while (self.b[h as usize] != 0) && (self.b[h as usize] != addr) {
             h = (h + 1) & (ICAO_FILTER_SIZE - 1);
             if h == h0 {
                 break 'loop_b;
             }
         }
 
-        if icao_filter_b[h as usize] == addr {
-            return true;
-        }
+        self.b[h as usize] == addr
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expire() {
+        let ttl_12mhz = 60 * 12_000_000;
+        let mut filter = IcaoFilter::default();
+        filter.expire(0);
+
+        filter.add(0x00ad_9293);
+        assert!(filter.test(0x00ad_9293));
 
-    false
+        // not yet expired
+        filter.expire(ttl_12mhz - 1);
+        assert!(filter.test(0x00ad_9293));
+
+        // moved to table B
+        filter.expire(ttl_12mhz);
+        assert!(filter.test(0x00ad_9293));
+
+        // dropped
+        filter.expire(2 * ttl_12mhz);
+        assert!(!filter.test(0x00ad_9293));
+    }
+
+    #[test]
+    fn expire_full() {
+        let mut filter = IcaoFilter::default();
+        filter.expire(0);
+        for addr in 1..=ICAO_FILTER_SIZE {
+            filter.add(addr);
+        }
+        filter.add(0x00ad_9293);
+        assert!(!filter.test(0x00ad_9293));
+
+        // a full table accepts addresses again once expired
+        filter.expire(ICAO_FILTER_TTL.as_secs() * 12_000_000);
+        filter.add(0x00ad_9293);
+        assert!(filter.test(0x00ad_9293));
+    }
 }
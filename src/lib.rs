@@ -8,8 +8,23 @@ timing, and things like that might give results that are not quite identical.
 #![allow(non_snake_case)]
 #![allow(non_camel_case_types)]
 
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use num_complex::Complex;
+
+pub use error::Error;
+
 // public
+pub mod convert;
+pub mod cpr;
+pub mod demod_2000;
 pub mod demod_2400;
+pub mod demod_hirate;
+pub mod error;
+pub mod mode_ac;
+pub mod mode_s;
+pub mod net_io;
+pub mod track;
 
 // public(crate)
 pub mod utils;
@@ -17,28 +32,108 @@ pub mod utils;
 // private
 mod crc;
 pub mod icao_filter;
-mod mode_s;
 
 pub const MODES_MAG_BUF_SAMPLES: usize = 131_072;
 
-const TRAILING_SAMPLES: usize = 326;
+/// Samples carried over to the next buffer, enough for the highest sample rate
+const TRAILING_SAMPLES: usize = SampleRate::Rate12000.trailing_samples();
 pub const MODES_LONG_MSG_BYTES: usize = 14;
 pub const MODES_SHORT_MSG_BYTES: usize = 7;
 
+/// Sample rates with a demodulator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SampleRate {
+    /// 2.0MHz, demodulated by [`Demodulator::demodulate2000`]
+    Rate2000,
+    /// 2.4MHz, demodulated by [`Demodulator::demodulate2400`]
+    #[default]
+    Rate2400,
+    /// 6MHz, demodulated by [`Demodulator::demodulate_hirate`]
+    Rate6000,
+    /// 8MHz, demodulated by [`Demodulator::demodulate_hirate`]
+    Rate8000,
+    /// 12MHz, demodulated by [`Demodulator::demodulate_hirate`]
+    Rate12000,
+}
+
+impl SampleRate {
+    /// Sample rate for `hz`, if supported
+    #[must_use]
+    pub fn from_hz(hz: u32) -> Option<Self> {
+        match hz {
+            2_000_000 => Some(Self::Rate2000),
+            2_400_000 => Some(Self::Rate2400),
+            6_000_000 => Some(Self::Rate6000),
+            8_000_000 => Some(Self::Rate8000),
+            12_000_000 => Some(Self::Rate12000),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub const fn hz(self) -> u32 {
+        match self {
+            Self::Rate2000 => 2_000_000,
+            Self::Rate2400 => 2_400_000,
+            Self::Rate6000 => 6_000_000,
+            Self::Rate8000 => 8_000_000,
+            Self::Rate12000 => 12_000_000,
+        }
+    }
+
+    // dump1090.h MODES_TRAILING_SAMPLES
+    /// Samples needed after the start of a message to hold a long message
+    #[inline(always)]
+    const fn trailing_samples(self) -> usize {
+        (8 + 112 + 16) * self.hz() as usize / 1_000_000
+    }
+
+    /// Whole samples per half bit of 0.5us, at least one
+    #[inline(always)]
+    const fn half_bit_samples(self) -> usize {
+        let samples = self.hz() as usize / 2_000_000;
+        if samples == 0 { 1 } else { samples }
+    }
+
+    /// Ticks of the 12MHz clock in `samples`
+    #[inline(always)]
+    const fn samples_to_ticks(self, samples: u64) -> u64 {
+        samples * 12_000_000 / self.hz() as u64
+    }
+
+    /// Duration of `samples`
+    #[inline(always)]
+    fn samples_to_duration(self, samples: u64) -> Duration {
+        Duration::from_nanos(samples * 1_000_000_000 / u64::from(self.hz()))
+    }
+}
+
 // dump1090.h:252
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct MagnitudeBuffer {
-    pub data: [u16; TRAILING_SAMPLES + MODES_MAG_BUF_SAMPLES],
+    /// `TRAILING_SAMPLES` carried over from the previous buffer, then up to
+    /// `MODES_MAG_BUF_SAMPLES` new samples. On the heap, so buffers are cheap to move
+    pub data: Box<[u16]>,
     pub length: usize,
-    pub first_sample_timestamp_12mhz: usize,
+    /// 12MHz clock tick of the first new sample, `data[TRAILING_SAMPLES]`
+    pub first_sample_timestamp_12mhz: u64,
+    /// Wall clock time of the first new sample, `data[TRAILING_SAMPLES]`
+    pub sys_timestamp: SystemTime,
+    pub sample_rate: SampleRate,
+    /// Count of samples received before the first new sample, for exact ticks at sample rates
+    /// with a fraction of a tick per sample
+    sample_counter: u64,
 }
 
 impl Default for MagnitudeBuffer {
     fn default() -> Self {
         Self {
-            data: [0_u16; TRAILING_SAMPLES + MODES_MAG_BUF_SAMPLES],
+            data: vec![0_u16; TRAILING_SAMPLES + MODES_MAG_BUF_SAMPLES].into_boxed_slice(),
             length: 0,
             first_sample_timestamp_12mhz: 0,
+            sys_timestamp: UNIX_EPOCH,
+            sample_rate: SampleRate::default(),
+            sample_counter: 0,
         }
     }
 }
@@ -48,4 +143,190 @@ impl MagnitudeBuffer {
         self.data[TRAILING_SAMPLES + self.length] = x;
         self.length += 1;
     }
+
+    /// Set the timestamps of this buffer from the first newly pushed sample
+    ///
+    /// `sample_counter` is the count of samples received before this buffer, and `sys_timestamp`
+    /// the wall clock time the first of the new samples was received.
+    pub fn set_timestamps(&mut self, sample_counter: u64, sys_timestamp: SystemTime) {
+        self.first_sample_timestamp_12mhz = self.sample_rate.samples_to_ticks(sample_counter);
+        self.sys_timestamp = sys_timestamp;
+        self.sample_counter = sample_counter;
+    }
+
+    /// Index of the first of the trailing samples of the sample rate. The demodulators start from
+    /// here, as `data` holds enough trailing samples for the highest sample rate
+    #[inline(always)]
+    fn trailing_start(&self) -> usize {
+        TRAILING_SAMPLES - self.sample_rate.trailing_samples()
+    }
+
+    /// Count of samples received before `data[index]`
+    #[inline(always)]
+    fn sample_offset_at(&self, index: usize) -> u64 {
+        (self.sample_counter + index as u64).saturating_sub(TRAILING_SAMPLES as u64)
+    }
+
+    /// 12MHz clock tick and wall clock time of `data[index]`
+    #[inline(always)]
+    fn timestamps_at(&self, index: usize) -> (u64, SystemTime) {
+        let index = index as u64;
+        let trailing = TRAILING_SAMPLES as u64;
+        let rate = self.sample_rate;
+        // ticks between two sample counts, rounded the same in every buffer
+        let first = rate.samples_to_ticks(self.sample_counter);
+        if index >= trailing {
+            let ticks = rate.samples_to_ticks(self.sample_counter + index - trailing) - first;
+            (
+                self.first_sample_timestamp_12mhz + ticks,
+                self.sys_timestamp + rate.samples_to_duration(index - trailing),
+            )
+        } else {
+            let before = self.sample_counter.saturating_sub(trailing - index);
+            let ticks = first - rate.samples_to_ticks(before);
+            (
+                self.first_sample_timestamp_12mhz.saturating_sub(ticks),
+                self.sys_timestamp - rate.samples_to_duration(trailing - index),
+            )
+        }
+    }
+}
+
+/// Configuration of a [`Demodulator`]
+#[derive(Debug, Clone)]
+pub struct DemodulatorConfig {
+    /// Time an address stays in the ICAO filter, see [`icao_filter::IcaoFilter`]
+    pub icao_filter_ttl: Duration,
+    /// Maximum number of bit errors fixed by CRC error correction of DF11/DF17/DF18, 0 to 2
+    ///
+    /// DF11 is only ever corrected by a single bit, as two bit errors are ambiguous.
+    pub fix_errors: usize,
+    /// Format of the raw samples given to [`Demodulator::demodulate_bytes`]
+    pub format: convert::SampleFormat,
+    /// Sample rate of the samples given to [`Demodulator::demodulate`] and
+    /// [`Demodulator::demodulate_bytes`]
+    pub sample_rate: SampleRate,
+}
+
+impl Default for DemodulatorConfig {
+    fn default() -> Self {
+        Self {
+            icao_filter_ttl: icao_filter::ICAO_FILTER_TTL,
+            fix_errors: 0,
+            format: convert::SampleFormat::default(),
+            sample_rate: SampleRate::default(),
+        }
+    }
+}
+
+/// Demodulator for one stream of samples
+///
+/// Owns the state that must not be shared between receivers: the ICAO address filter and the
+/// running sample count used for timestamps.
+#[derive(Debug, Default)]
+pub struct Demodulator {
+    icao_filter: icao_filter::IcaoFilter,
+    converter: utils::MagnitudeConverter,
+    config: DemodulatorConfig,
+}
+
+impl Demodulator {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_config(config: DemodulatorConfig) -> Self {
+        Self {
+            icao_filter: icao_filter::IcaoFilter::new(config.icao_filter_ttl),
+            converter: utils::MagnitudeConverter::new(config.format)
+                .with_sample_rate(config.sample_rate),
+            config,
+        }
+    }
+
+    #[must_use]
+    pub fn config(&self) -> &DemodulatorConfig {
+        &self.config
+    }
+
+    /// Convert and demodulate the next block of samples, the first of which was received at
+    /// `sys_timestamp`
+    pub fn demodulate(
+        &mut self,
+        data: &[Complex<i16>],
+        sys_timestamp: SystemTime,
+    ) -> Result<Vec<demod_2400::ModeSMessage>, Error> {
+        let mag = self.convert(data, sys_timestamp)?;
+        self.demodulate_mag(&mag)
+    }
+
+    /// Convert and demodulate the next block of raw samples in the configured
+    /// [`DemodulatorConfig::format`], the first of which was received at `sys_timestamp`
+    pub fn demodulate_bytes(
+        &mut self,
+        data: &[u8],
+        sys_timestamp: SystemTime,
+    ) -> Result<Vec<demod_2400::ModeSMessage>, Error> {
+        let mag = self.convert_bytes(data, sys_timestamp)?;
+        self.demodulate_mag(&mag)
+    }
+
+    /// Convert the next block of samples, for demodulating with both [`Self::demodulate_mag`]
+    /// and [`Self::demodulate_mode_ac`]
+    ///
+    /// More than `MODES_MAG_BUF_SAMPLES` samples is an [`Error::BufferOverflow`].
+    pub fn convert(
+        &mut self,
+        data: &[Complex<i16>],
+        sys_timestamp: SystemTime,
+    ) -> Result<MagnitudeBuffer, Error> {
+        self.converter.to_mag(data, sys_timestamp)
+    }
+
+    /// [`Self::convert`] for raw samples in the configured [`DemodulatorConfig::format`]
+    ///
+    /// `data` ending within a sample is an [`Error::MalformedInput`].
+    pub fn convert_bytes(
+        &mut self,
+        data: &[u8],
+        sys_timestamp: SystemTime,
+    ) -> Result<MagnitudeBuffer, Error> {
+        self.converter.to_mag_bytes(data, sys_timestamp)
+    }
+
+    /// Demodulate with the demodulator for the sample rate of `mag`
+    pub fn demodulate_mag(
+        &mut self,
+        mag: &MagnitudeBuffer,
+    ) -> Result<Vec<demod_2400::ModeSMessage>, Error> {
+        match mag.sample_rate {
+            SampleRate::Rate2000 => self.demodulate2000(mag),
+            SampleRate::Rate2400 => self.demodulate2400(mag),
+            SampleRate::Rate6000 | SampleRate::Rate8000 | SampleRate::Rate12000 => {
+                self.demodulate_hirate(mag)
+            }
+        }
+    }
+
+    // dump1090.c backgroundTasks, icaoFilterExpire
+    /// Age the ICAO filter at the wall clock time `sys_timestamp`, once per buffer or frame
+    ///
+    /// The filter is aged on the wall clock rather than the 12MHz clock of the samples, as frames
+    /// from [`Self::decode_frame`] carry the clocks of other receivers. Buffers given the time of
+    /// their samples, such as from a recording, age it with the recording.
+    fn expire_icao_filter(&mut self, sys_timestamp: SystemTime) {
+        let since_epoch = sys_timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+        self.icao_filter.expire((since_epoch.as_nanos() * 12 / 1_000) as u64);
+    }
+
+    #[must_use]
+    pub fn icao_filter(&self) -> &icao_filter::IcaoFilter {
+        &self.icao_filter
+    }
+
+    pub fn icao_filter_mut(&mut self) -> &mut icao_filter::IcaoFilter {
+        &mut self.icao_filter
+    }
 }
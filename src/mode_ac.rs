@@ -0,0 +1,363 @@
+// This module includes functionality translated from mode_ac.c. The demodulator is not a
+// translation of demod_2400.c demodulate2400AC, which only handles 2.4MHz
+
+use std::time::SystemTime;
+
+use crate::{Demodulator, MagnitudeBuffer, mode_s::Squawk};
+
+pub const INVALID_ALTITUDE: i32 = -9999;
+
+/// Spacing of the pulses of a Mode A/C reply, in us
+const MODE_AC_PULSE_SPACING_US: f64 = 1.45;
+/// Width of the pulses of a Mode A/C reply, in us
+const MODE_AC_PULSE_WIDTH_US: f64 = 0.45;
+/// Pulse position of F2, with F1 at 0
+const MODE_AC_F2: usize = 14;
+/// Pulse position of SPI
+const MODE_AC_SPI: usize = 17;
+/// Pulse positions of a reply, up to and including X5
+const MODE_AC_PULSES: usize = 20;
+/// Pulse positions that are always empty: X1 between the A/C and B/D pulses, and X2 to X5 around
+/// SPI
+const MODE_AC_QUIET: [usize; 5] = [7, 15, 16, 18, 19];
+
+/// Bit of each pulse position in the reply code, in the layout of [`Squawk`] with SPI in
+/// `0x0080`. Positions 0, 7 and 14 are F1, X1 and F2.
+const MODE_AC_BITS: [(usize, u16); 13] = [
+    (1, 0x0010),  // C1
+    (2, 0x1000),  // A1
+    (3, 0x0020),  // C2
+    (4, 0x2000),  // A2
+    (5, 0x0040),  // C4
+    (6, 0x4000),  // A4
+    (8, 0x0100),  // B1
+    (9, 0x0001),  // D1
+    (10, 0x0200), // B2
+    (11, 0x0002), // D2
+    (12, 0x0400), // B4
+    (13, 0x0004), // D4
+    (MODE_AC_SPI, 0x0080),
+];
+
+/// Mode A/C reply, from [`Demodulator::demodulate_mode_ac`]
+///
+/// A reply carries no address and doesn't say whether it answers a Mode A (identity) or Mode C
+/// (altitude) interrogation, so both readings are offered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModeACMessage {
+    /// Reply code in the layout of [`Squawk`], with SPI in `0x0080`
+    mode_a: u16,
+    /// RSSI, in the range [0..1], as a fraction of full-scale power
+    signal_level: f64,
+    /// 12MHz clock tick of the start of F1
+    timestamp_12mhz: u64,
+    /// Wall clock time of the start of F1
+    sys_timestamp: SystemTime,
+    /// Count of samples received before the start of F1
+    sample_offset: u64,
+}
+
+impl ModeACMessage {
+    /// Reply from network input, with the reply code of [`Self::buffer`] and no sample offset
+    pub(crate) fn from_buffer(
+        buffer: [u8; 2],
+        signal_level: f64,
+        timestamp_12mhz: u64,
+        sys_timestamp: SystemTime,
+    ) -> Self {
+        Self {
+            mode_a: u16::from_be_bytes(buffer),
+            signal_level,
+            timestamp_12mhz,
+            sys_timestamp,
+            sample_offset: 0,
+        }
+    }
+
+    /// Reply code as sent in Beast output, big-endian
+    #[inline(always)]
+    #[must_use]
+    pub fn buffer(&self) -> [u8; 2] {
+        self.mode_a.to_be_bytes()
+    }
+
+    /// Reply code in the layout of [`Squawk`], with SPI in `0x0080`
+    #[must_use]
+    pub fn mode_a(&self) -> u16 {
+        self.mode_a
+    }
+
+    /// Reply read as a Mode A identity code
+    #[must_use]
+    pub fn squawk(&self) -> Squawk {
+        Squawk(self.mode_a & 0x7777)
+    }
+
+    /// Special position identification pulse, set while the pilot presses IDENT
+    #[must_use]
+    pub fn spi(&self) -> bool {
+        self.mode_a & 0x0080 != 0
+    }
+
+    /// Reply read as a Mode C altitude in feet, if it is a valid Gillham code
+    #[must_use]
+    pub fn altitude(&self) -> Option<i32> {
+        // SPI is never sent in a Mode C reply
+        if self.spi() {
+            return None;
+        }
+        match mode_a_to_mode_c(u32::from(self.mode_a)) {
+            INVALID_ALTITUDE => None,
+            n => Some(n * 100),
+        }
+    }
+
+    /// 12MHz clock tick of the start of F1, for MLAT
+    #[inline(always)]
+    #[must_use]
+    pub fn timestamp_12mhz(&self) -> u64 {
+        self.timestamp_12mhz
+    }
+
+    /// Wall clock time of the start of F1
+    #[inline(always)]
+    #[must_use]
+    pub fn sys_timestamp(&self) -> SystemTime {
+        self.sys_timestamp
+    }
+
+    /// Count of samples received before the start of F1, as counted by
+    /// [`MagnitudeBuffer::set_timestamps`]
+    #[inline(always)]
+    #[must_use]
+    pub fn sample_offset(&self) -> u64 {
+        self.sample_offset
+    }
+
+    /// Power of the framing pulses, as a fraction of full-scale power
+    #[inline(always)]
+    #[must_use]
+    pub fn signal_level(&self) -> f64 {
+        self.signal_level
+    }
+
+    /// [`Self::signal_level`] in dBFS, 0 at full scale
+    #[inline(always)]
+    #[must_use]
+    pub fn rssi_dbfs(&self) -> f64 {
+        10.0 * self.signal_level.log10()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ModeACMessage {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut s = serializer.serialize_struct("ModeACMessage", 6)?;
+        s.serialize_field("mode_a", &self.mode_a)?;
+        s.serialize_field("signal_level", &self.signal_level)?;
+        s.serialize_field("rssi_dbfs", &self.rssi_dbfs())?;
+        s.serialize_field("sample_offset", &self.sample_offset)?;
+        s.serialize_field("timestamp_12mhz", &self.timestamp_12mhz)?;
+        s.serialize_field("sys_timestamp", &self.sys_timestamp)?;
+        s.end()
+    }
+}
+
+impl Demodulator {
+    /// Search `mag` for Mode A/C replies, at the sample rate of `mag`
+    ///
+    /// A reply is a pair of framing pulses F1 and F2, 20.3us apart, with up to 13 pulses between
+    /// them and SPI after F2. From each rising edge above twice the mean level, the centers of F1
+    /// and F2 are found as the centroids of the samples around their nominal positions, giving
+    /// the pulse spacing. Each pulse position is the sum of the samples within a pulse width of
+    /// its center. A reply is only taken when F1 and F2 are within a factor of 2 of each other,
+    /// every pulse position is above half or below a quarter of their level, and the positions
+    /// that are always empty and the single samples between positions are quiet, which rules
+    /// out Mode S.
+    #[must_use]
+    pub fn demodulate_mode_ac(&self, mag: &MagnitudeBuffer) -> Vec<ModeACMessage> {
+        let mut results = vec![];
+
+        let offset = mag.trailing_start();
+        let data = &mag.data[offset..];
+
+        let samples_per_us = f64::from(mag.sample_rate.hz()) / 1_000_000.0;
+        let spacing = MODE_AC_PULSE_SPACING_US * samples_per_us;
+        let width = MODE_AC_PULSE_WIDTH_US * samples_per_us;
+        // samples within `reach` of the center of a pulse hold some of it
+        let reach = width / 2.0 + 0.5;
+
+        // the noise floor, from the mean level of the buffer
+        let new = &data[..mag.sample_rate.trailing_samples() + mag.length];
+        let mean = new.iter().map(|&m| u64::from(m)).sum::<u64>() / new.len().max(1) as u64;
+        // at most the full scale, as a saturated buffer has a mean above half of it
+        let noise_level = (mean * 2).clamp(1, u64::from(u16::MAX)) as u16;
+
+        // sum of the samples of a pulse centered at `x`
+        let level = |x: f64| -> u32 {
+            let first = (x - reach).ceil().max(0.0) as usize;
+            let last = (x + reach).floor() as usize;
+            data[first..=last].iter().map(|&m| u32::from(m)).sum()
+        };
+        // center of the pulse around `x`, weighted by the level above the mean
+        let centroid = |x: f64| -> Option<f64> {
+            let first = (x - reach - 1.0).ceil().max(0.0) as usize;
+            let last = (x + reach + 1.0).floor() as usize;
+            let (mut sum, mut moment) = (0.0, 0.0);
+            for (i, &m) in data.iter().enumerate().take(last + 1).skip(first) {
+                let w = f64::from(m.saturating_sub(mean as u16));
+                sum += w;
+                moment += w * i as f64;
+            }
+            (sum > 0.0).then(|| moment / sum)
+        };
+
+        let mut skip_count: usize = 0;
+        'jloop: for j in 1..mag.length {
+            if skip_count > 0 {
+                skip_count -= 1;
+                continue 'jloop;
+            }
+
+            // the rising edge of F1
+            if data[j] < noise_level || data[j - 1] >= noise_level {
+                continue 'jloop;
+            }
+
+            // F1 and F2, and the pulse spacing between them, within 0.1us of nominal over the
+            // whole reply
+            let Some(f1_center) = centroid(j as f64 + width / 2.0) else {
+                continue 'jloop;
+            };
+            let Some(f2_center) = centroid(f1_center + MODE_AC_F2 as f64 * spacing) else {
+                continue 'jloop;
+            };
+            let pulse_spacing = (f2_center - f1_center) / MODE_AC_F2 as f64;
+            if (pulse_spacing - spacing).abs() * MODE_AC_F2 as f64 > 0.1 * samples_per_us {
+                continue 'jloop;
+            }
+            let pulse = |k: usize| level(f1_center + k as f64 * pulse_spacing);
+
+            // F1 and F2 of about the same level
+            let (f1, f2) = (pulse(0), pulse(MODE_AC_F2));
+            if f1 / 2 > f2 || f2 / 2 > f1 {
+                continue 'jloop;
+            }
+            let signal = (f1 + f2) / 2;
+            let high = signal / 2;
+            let low = signal / 4;
+
+            // single samples halfway between pulse positions, against the peak of a pulse
+            let gap_low = (f64::from(low) / width.max(1.0)) as u32;
+            let gap = |k: usize| {
+                let x = f1_center + (k as f64 + 0.5) * pulse_spacing;
+                u32::from(data[x.round() as usize])
+            };
+
+            if MODE_AC_QUIET.iter().any(|&k| pulse(k) >= low)
+                || (0..MODE_AC_SPI).any(|k| gap(k) >= gap_low)
+            {
+                continue 'jloop;
+            }
+
+            let mut mode_a = 0;
+            for (k, bit) in MODE_AC_BITS {
+                let level = pulse(k);
+                if level >= high {
+                    mode_a |= bit;
+                } else if level >= low {
+                    // neither high nor low
+                    continue 'jloop;
+                }
+            }
+
+            let f1_start = (f1_center - width / 2.0).round().max(0.0) as usize;
+            let (timestamp_12mhz, sys_timestamp) = mag.timestamps_at(offset + f1_start);
+            // peak level of F1 and F2, as a fraction of full scale
+            let signal = f64::from(signal) / width.max(1.0) / 65535.0;
+            results.push(ModeACMessage {
+                mode_a,
+                signal_level: (signal * signal).min(1.0),
+                timestamp_12mhz,
+                sys_timestamp,
+                sample_offset: mag.sample_offset_at(offset + f1_start),
+            });
+
+            // skip to the end of the reply
+            skip_count = (spacing * MODE_AC_PULSES as f64) as usize;
+        }
+
+        results
+    }
+}
+
+// mode_ac.c ModeAToModeC
+/// Convert a Gillham coded Mode A value to a Mode C altitude, in hundreds of feet
+#[must_use]
+pub fn mode_a_to_mode_c(mode_a: u32) -> i32 {
+    let mut five_hundreds: u32 = 0;
+    let mut one_hundreds: u32 = 0;
+
+    // check zero bits are zero, D1 set is illegal
+    // C1,,C4 cannot be Zero
+    if (mode_a & 0xffff_8889) != 0 || (mode_a & 0x0000_00f0) == 0 {
+        return INVALID_ALTITUDE;
+    }
+
+    if mode_a & 0x0010 != 0 {
+        one_hundreds ^= 0x007; // C1
+    }
+    if mode_a & 0x0020 != 0 {
+        one_hundreds ^= 0x003; // C2
+    }
+    if mode_a & 0x0040 != 0 {
+        one_hundreds ^= 0x001; // C4
+    }
+
+    // Remove 7s from OneHundreds (Make 7->5, snd 5->7).
+    if (one_hundreds & 5) == 5 {
+        one_hundreds ^= 2;
+    }
+
+    // Check for invalid codes, only 1 to 5 are valid
+    if one_hundreds > 5 {
+        return INVALID_ALTITUDE;
+    }
+
+    // D1 never used for altitude
+    if mode_a & 0x0002 != 0 {
+        five_hundreds ^= 0x0ff; // D2
+    }
+    if mode_a & 0x0004 != 0 {
+        five_hundreds ^= 0x07f; // D4
+    }
+
+    if mode_a & 0x1000 != 0 {
+        five_hundreds ^= 0x03f; // A1
+    }
+    if mode_a & 0x2000 != 0 {
+        five_hundreds ^= 0x01f; // A2
+    }
+    if mode_a & 0x4000 != 0 {
+        five_hundreds ^= 0x00f; // A4
+    }
+
+    if mode_a & 0x0100 != 0 {
+        five_hundreds ^= 0x007; // B1
+    }
+    if mode_a & 0x0200 != 0 {
+        five_hundreds ^= 0x003; // B2
+    }
+    if mode_a & 0x0400 != 0 {
+        five_hundreds ^= 0x001; // B4
+    }
+
+    // Correct order of OneHundreds.
+    if five_hundreds & 1 != 0 {
+        one_hundreds = 6 - one_hundreds;
+    }
+
+    (five_hundreds * 5 + one_hundreds) as i32 - 13
+}
@@ -0,0 +1,440 @@
+// This module includes functionality translated from mode_s.c decodeModesMessage
+
+use std::fmt;
+
+use super::{decode_ac12_field, decode_ac13_field, decode_callsign, decode_id13_field, getbits};
+use crate::{Error, MODES_LONG_MSG_BYTES, MODES_SHORT_MSG_BYTES};
+use crate::{crc::modes_checksum, demod_2400::ModeSMessage};
+
+/// Mode A identity code, as the hex digits of the octal code, `0x7700` for 7700
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Squawk(pub u16);
+
+impl Squawk {
+    /// Emergency codes 7500 (hijack), 7600 (radio failure) and 7700 (emergency)
+    #[must_use]
+    pub fn is_emergency(&self) -> bool {
+        matches!(self.0, 0x7500 | 0x7600 | 0x7700)
+    }
+}
+
+impl fmt::Display for Squawk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04x}", self.0)
+    }
+}
+
+/// FS field of DF4, DF5, DF20 and DF21
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlightStatus {
+    Airborne,
+    OnGround,
+    AlertAirborne,
+    AlertOnGround,
+    AlertSpi,
+    Spi,
+    Reserved(u8),
+}
+
+impl From<u8> for FlightStatus {
+    fn from(fs: u8) -> Self {
+        match fs {
+            0 => Self::Airborne,
+            1 => Self::OnGround,
+            2 => Self::AlertAirborne,
+            3 => Self::AlertOnGround,
+            4 => Self::AlertSpi,
+            5 => Self::Spi,
+            _ => Self::Reserved(fs),
+        }
+    }
+}
+
+impl FlightStatus {
+    #[must_use]
+    pub fn on_ground(&self) -> Option<bool> {
+        match self {
+            Self::Airborne | Self::AlertAirborne => Some(false),
+            Self::OnGround | Self::AlertOnGround => Some(true),
+            _ => None,
+        }
+    }
+
+    /// Mode A code changed
+    #[must_use]
+    pub fn alert(&self) -> bool {
+        matches!(self, Self::AlertAirborne | Self::AlertOnGround | Self::AlertSpi)
+    }
+
+    /// Special position identification, the pilot pressed IDENT
+    #[must_use]
+    pub fn spi(&self) -> bool {
+        matches!(self, Self::AlertSpi | Self::Spi)
+    }
+}
+
+/// CA field of DF11 and DF17
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capability {
+    Level1,
+    Reserved(u8),
+    OnGround,
+    Airborne,
+    OnGroundOrAirborne,
+    /// Flight status is in the DR field
+    FlightStatusInDr,
+}
+
+impl From<u8> for Capability {
+    fn from(ca: u8) -> Self {
+        match ca {
+            0 => Self::Level1,
+            4 => Self::OnGround,
+            5 => Self::Airborne,
+            6 => Self::OnGroundOrAirborne,
+            7 => Self::FlightStatusInDr,
+            _ => Self::Reserved(ca),
+        }
+    }
+}
+
+impl Capability {
+    #[must_use]
+    pub fn on_ground(&self) -> Option<bool> {
+        match self {
+            Self::OnGround => Some(true),
+            Self::Airborne => Some(false),
+            _ => None,
+        }
+    }
+}
+
+/// Raw Compact Position Reporting fields of a position extended squitter
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CprFrame {
+    /// Odd (`true`) or even (`false`) encoding
+    pub odd: bool,
+    /// 17 bit encoded latitude
+    pub lat: u32,
+    /// 17 bit encoded longitude
+    pub lon: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AltitudeSource {
+    /// Barometric altitude
+    Baro,
+    /// GNSS height above the ellipsoid
+    Gnss,
+}
+
+/// Source of the vertical rate of an airborne velocity
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerticalRateSource {
+    Gnss,
+    Baro,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AirspeedType {
+    Indicated,
+    True,
+}
+
+/// Airborne velocity, type code 19
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Velocity {
+    /// Ground speed in knots, subtypes 1 and 2
+    pub ground_speed: Option<f64>,
+    /// Track over ground in degrees, subtypes 1 and 2
+    pub track: Option<f64>,
+    /// Airspeed in knots and its type, subtypes 3 and 4
+    pub airspeed: Option<(u32, AirspeedType)>,
+    /// Magnetic heading in degrees, subtypes 3 and 4
+    pub heading: Option<f64>,
+    /// Vertical rate in feet per minute, positive when climbing
+    pub vertical_rate: Option<i32>,
+    pub vertical_rate_source: Option<VerticalRateSource>,
+    /// Difference of GNSS height over barometric altitude, in feet
+    pub gnss_baro_diff: Option<i32>,
+}
+
+/// Decoded ME field of DF17 and DF18 extended squitters
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExtendedSquitter {
+    /// Type code 0, no position information
+    NoPosition,
+    /// Type codes 1 to 4
+    Identification {
+        /// Emitter category, set A to D in the high nibble, `0xA3` for A3
+        category: u8,
+        callsign: String,
+    },
+    /// Type codes 5 to 8
+    SurfacePosition {
+        /// Ground speed in knots
+        ground_speed: Option<f64>,
+        /// Track over ground in degrees
+        track: Option<f64>,
+        cpr: CprFrame,
+    },
+    /// Type codes 9 to 18 and 20 to 22
+    AirbornePosition {
+        /// Altitude in feet
+        altitude: Option<i32>,
+        altitude_source: AltitudeSource,
+        surveillance_status: u8,
+        cpr: CprFrame,
+    },
+    /// Type code 19
+    AirborneVelocity(Velocity),
+    /// Type code 28, subtype 1
+    EmergencyStatus { emergency: u8, squawk: Squawk },
+    /// Type code 31
+    OperationalStatus {
+        /// ADS-B version number
+        version: u8,
+    },
+    /// Type codes and subtypes not decoded
+    Unknown { type_code: u8, subtype: u8 },
+}
+
+/// Fields decoded from a Mode S message
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodedMessage {
+    /// Downlink format
+    pub df: u8,
+    /// ICAO address, for address/parity messages recovered from the CRC
+    pub icao: u32,
+    /// AC13 altitude of DF0, DF4, DF16 and DF20, in feet
+    pub altitude: Option<i32>,
+    /// ID13 identity of DF5 and DF21
+    pub squawk: Option<Squawk>,
+    /// Flight status of DF4, DF5, DF20 and DF21
+    pub flight_status: Option<FlightStatus>,
+    /// Capability of DF11 and DF17
+    pub capability: Option<Capability>,
+    /// Vertical status of DF0 and DF16, `true` when on ground
+    pub vertical_status: Option<bool>,
+    /// Decoded ME field of DF17 and DF18
+    pub me: Option<ExtendedSquitter>,
+}
+
+impl DecodedMessage {
+    /// Whether the aircraft is on ground, if any field of the message tells
+    #[must_use]
+    pub fn on_ground(&self) -> Option<bool> {
+        match &self.me {
+            Some(ExtendedSquitter::SurfacePosition { .. }) => return Some(true),
+            Some(ExtendedSquitter::AirbornePosition { .. }) => return Some(false),
+            _ => (),
+        }
+        self.flight_status
+            .and_then(|fs| fs.on_ground())
+            .or_else(|| self.capability.and_then(|ca| ca.on_ground()))
+            .or(self.vertical_status)
+    }
+
+    /// Squawk from the identity field or an emergency status extended squitter
+    #[must_use]
+    pub fn squawk(&self) -> Option<Squawk> {
+        match &self.me {
+            Some(ExtendedSquitter::EmergencyStatus { squawk, .. }) => Some(*squawk),
+            _ => self.squawk,
+        }
+    }
+}
+
+impl ModeSMessage {
+    /// Decode the fields of this message
+    #[must_use]
+    pub fn decode(&self) -> DecodedMessage {
+        decode_fields(self.buffer())
+    }
+}
+
+/// Decode the fields of a 56 or 112 bit Mode S message with a valid CRC
+///
+/// A message of any other length is an [`Error::MalformedInput`].
+pub fn decode(msg: &[u8]) -> Result<DecodedMessage, Error> {
+    if ![MODES_SHORT_MSG_BYTES, MODES_LONG_MSG_BYTES].contains(&msg.len()) {
+        return Err(Error::MalformedInput(format!("mode s message of {} bytes", msg.len())));
+    }
+    Ok(decode_fields(msg))
+}
+
+/// [`decode`] for a message of 7 or 14 bytes
+fn decode_fields(msg: &[u8]) -> DecodedMessage {
+    let msgbits = msg.len() * 8;
+    let df = getbits(msg, 1, 5) as u8;
+
+    let icao = match df {
+        11 | 17 | 18 => getbits(msg, 9, 32) as u32,
+        _ => modes_checksum(msg, msgbits),
+    };
+
+    let mut decoded = DecodedMessage {
+        df,
+        icao,
+        altitude: None,
+        squawk: None,
+        flight_status: None,
+        capability: None,
+        vertical_status: None,
+        me: None,
+    };
+
+    match df {
+        0 | 4 | 16 | 20 => decoded.altitude = decode_ac13_field(getbits(msg, 20, 32) as u32),
+        5 | 21 => {
+            decoded.squawk = Some(Squawk(decode_id13_field(getbits(msg, 20, 32) as u32) as u16))
+        }
+        _ => (),
+    }
+
+    match df {
+        0 | 16 => decoded.vertical_status = Some(getbits(msg, 6, 6) != 0),
+        4 | 5 | 20 | 21 => {
+            decoded.flight_status = Some(FlightStatus::from(getbits(msg, 6, 8) as u8))
+        }
+        11 | 17 => decoded.capability = Some(Capability::from(getbits(msg, 6, 8) as u8)),
+        _ => (),
+    }
+
+    if (df == 17 || df == 18) && msgbits == 112 {
+        decoded.me = Some(decode_extended_squitter(msg));
+    }
+
+    decoded
+}
+
+fn decode_cpr(msg: &[u8]) -> CprFrame {
+    CprFrame {
+        odd: getbits(msg, 54, 54) != 0,
+        lat: getbits(msg, 55, 71) as u32,
+        lon: getbits(msg, 72, 88) as u32,
+    }
+}
+
+// mode_s.c decodeMovementField
+/// Ground speed in knots of a surface position movement field
+fn decode_movement_field(movement: u32) -> Option<f64> {
+    let movement = f64::from(movement);
+    match movement as u32 {
+        0 | 125.. => None,
+        1 => Some(0.0),
+        2 => Some(0.125 / 2.0),
+        3..=8 => Some(0.125 + (movement - 3.0 + 0.5) * 0.125),
+        9..=12 => Some(1.0 + (movement - 9.0 + 0.5) * 0.25),
+        13..=38 => Some(2.0 + (movement - 13.0 + 0.5) * 0.5),
+        39..=93 => Some(15.0 + (movement - 39.0 + 0.5)),
+        94..=108 => Some(70.0 + (movement - 94.0 + 0.5) * 2.0),
+        109..=123 => Some(100.0 + (movement - 109.0 + 0.5) * 5.0),
+        // greater than 175kt
+        124 => Some(180.0),
+    }
+}
+
+fn decode_extended_squitter(msg: &[u8]) -> ExtendedSquitter {
+    let metype = getbits(msg, 33, 37) as u8;
+    let mesub = getbits(msg, 38, 40) as u8;
+
+    match metype {
+        0 => ExtendedSquitter::NoPosition,
+        1..=4 => ExtendedSquitter::Identification {
+            category: ((0x0e - metype) << 4) | mesub,
+            callsign: decode_callsign(msg),
+        },
+        5..=8 => {
+            let track = if getbits(msg, 45, 45) != 0 {
+                Some(getbits(msg, 46, 52) as f64 * 360.0 / 128.0)
+            } else {
+                None
+            };
+            ExtendedSquitter::SurfacePosition {
+                ground_speed: decode_movement_field(getbits(msg, 38, 44) as u32),
+                track,
+                cpr: decode_cpr(msg),
+            }
+        }
+        9..=18 | 20..=22 => {
+            let ac12_field = getbits(msg, 41, 52) as u32;
+            let (altitude, altitude_source) = if metype <= 18 {
+                let altitude = (ac12_field != 0).then(|| decode_ac12_field(ac12_field));
+                (altitude, AltitudeSource::Baro)
+            } else {
+                // GNSS height in meters
+                let altitude = (ac12_field != 0).then(|| (f64::from(ac12_field) * 3.28084) as i32);
+                (altitude, AltitudeSource::Gnss)
+            };
+            ExtendedSquitter::AirbornePosition {
+                altitude,
+                altitude_source,
+                surveillance_status: getbits(msg, 38, 39) as u8,
+                cpr: decode_cpr(msg),
+            }
+        }
+        19 if (1..=4).contains(&mesub) => {
+            ExtendedSquitter::AirborneVelocity(decode_velocity(msg, mesub))
+        }
+        28 if mesub == 1 => ExtendedSquitter::EmergencyStatus {
+            emergency: getbits(msg, 41, 43) as u8,
+            squawk: Squawk(decode_id13_field(getbits(msg, 44, 56) as u32) as u16),
+        },
+        31 => ExtendedSquitter::OperationalStatus { version: getbits(msg, 73, 75) as u8 },
+        _ => ExtendedSquitter::Unknown { type_code: metype, subtype: mesub },
+    }
+}
+
+fn decode_velocity(msg: &[u8], mesub: u8) -> Velocity {
+    let mut velocity = Velocity::default();
+    // supersonic subtypes use a 4 knot resolution
+    let scale = if mesub == 2 || mesub == 4 { 4 } else { 1 };
+
+    if mesub == 1 || mesub == 2 {
+        let ew_raw = getbits(msg, 47, 56) as i32;
+        let ns_raw = getbits(msg, 58, 67) as i32;
+        if ew_raw != 0 && ns_raw != 0 {
+            let ew_sign = if getbits(msg, 46, 46) != 0 { -1 } else { 1 };
+            let ns_sign = if getbits(msg, 57, 57) != 0 { -1 } else { 1 };
+            let ew_vel = f64::from((ew_raw - 1) * scale * ew_sign);
+            let ns_vel = f64::from((ns_raw - 1) * scale * ns_sign);
+
+            velocity.ground_speed = Some(ew_vel.hypot(ns_vel));
+            let track = ew_vel.atan2(ns_vel).to_degrees();
+            velocity.track = Some(if track < 0.0 { track + 360.0 } else { track });
+        }
+    } else {
+        if getbits(msg, 46, 46) != 0 {
+            velocity.heading = Some(getbits(msg, 47, 56) as f64 * 360.0 / 1024.0);
+        }
+        let airspeed = getbits(msg, 58, 67) as u32;
+        if airspeed != 0 {
+            let airspeed_type = if getbits(msg, 57, 57) != 0 {
+                AirspeedType::True
+            } else {
+                AirspeedType::Indicated
+            };
+            velocity.airspeed = Some(((airspeed - 1) * scale as u32, airspeed_type));
+        }
+    }
+
+    let vr_raw = getbits(msg, 70, 78) as i32;
+    if vr_raw != 0 {
+        let vr_sign = if getbits(msg, 69, 69) != 0 { -1 } else { 1 };
+        velocity.vertical_rate = Some((vr_raw - 1) * 64 * vr_sign);
+        velocity.vertical_rate_source = Some(if getbits(msg, 68, 68) != 0 {
+            VerticalRateSource::Baro
+        } else {
+            VerticalRateSource::Gnss
+        });
+    }
+
+    let diff_raw = getbits(msg, 82, 88) as i32;
+    if diff_raw != 0 {
+        let diff_sign = if getbits(msg, 81, 81) != 0 { -1 } else { 1 };
+        velocity.gnss_baro_diff = Some((diff_raw - 1) * 25 * diff_sign);
+    }
+
+    velocity
+}
@@ -2,11 +2,15 @@
 
 use crate::{
     MODES_LONG_MSG_BYTES, MODES_SHORT_MSG_BYTES,
+    crc::{ErrorInfo, modes_checksum, modes_checksum_diagnose},
     demod_2400::MsgLen,
-    icao_filter::{ICAO_FILTER_ADSB_NT, icao_filter_add},
+    icao_filter::{ICAO_FILTER_ADSB_NT, IcaoFilter},
+    mode_ac::mode_a_to_mode_c,
 };
 
-use super::{crc::modes_checksum, icao_filter::icao_filter_test};
+mod decode;
+
+pub use decode::*;
 
 // mode_s.c:215
 #[must_use]
@@ -29,9 +33,30 @@ pub fn getbits(data: &[u8], firstbit_1idx: usize, lastbit_1idx: usize) -> usize
     ans
 }
 
+// mode_s.c correct_aa_field
+/// Apply the bit errors in `info` that fall within the AA field to `addr`
+fn correct_aa_field(addr: u32, info: &ErrorInfo) -> u32 {
+    let mut addr = addr;
+    for bit in &info.bit[..info.errors] {
+        let bit = u32::from(*bit);
+        if (8..32).contains(&bit) {
+            addr ^= 1 << (31 - bit);
+        }
+    }
+    addr
+}
+
 // mode_s.c:289
+/// Score a possible message, correcting up to `fix_errors` bit errors of DF11/DF17/DF18
+///
+/// Returns the length, score and, for messages that are only valid once corrected, the bit
+/// errors to fix.
 #[must_use]
-pub fn score_modes_message(msg: &[u8]) -> Option<(MsgLen, i32)> {
+pub(crate) fn score_modes_message(
+    msg: &[u8],
+    icao_filter: &mut IcaoFilter,
+    fix_errors: usize,
+) -> Option<(MsgLen, i32, Option<ErrorInfo>)> {
     let validbits = msg.len() * 8;
 
     if validbits < MODES_SHORT_MSG_BYTES * 8 {
@@ -52,6 +77,7 @@ pub fn score_modes_message(msg: &[u8]) -> Option<(MsgLen, i32)> {
         return None;
     }
 
+    let mut corrected = None;
     let res = match df {
         0 | 4 | 5 => {
             // 0:  short air-air surveillance
@@ -68,43 +94,59 @@ pub fn score_modes_message(msg: &[u8]) -> Option<(MsgLen, i32)> {
             // 31: Comm-D (ELM)
 
             let crc = modes_checksum(msg, msgbits);
-            if icao_filter_test(crc) { 1000 } else { -1 }
+            if icao_filter.test(crc) { 1000 } else { -1 }
         }
         11 => {
             // 11: All-call reply
             let crc = modes_checksum(msg, msgbits);
             let iid = crc & 0x7f;
             let crc = crc & 0x00ff_ff80;
-            let addr = getbits(msg, 9, 32) as u32;
 
-            match (crc, iid, icao_filter_test(addr)) {
-                (0, 0, true) => 1600,
+            // two bit errors are ambiguous in DF11, only fix single bit errors
+            let Some(info) = modes_checksum_diagnose(crc, msgbits, fix_errors.min(1)) else {
+                return Some((msglen, -2, None));
+            };
+            let addr = correct_aa_field(getbits(msg, 9, 32) as u32, &info);
+            let divisor = info.errors as i32 + 1;
+            if info.errors != 0 {
+                corrected = Some(info);
+            }
+
+            match (iid, info.errors, icao_filter.test(addr)) {
+                (0, _, true) => 1600 / divisor,
                 (0, 0, false) => {
-                    icao_filter_add(addr);
+                    icao_filter.add(addr);
                     750
                 }
-                (0, _, true) => 1000,
-                (0, _, false) => -1,
-                (_, _, _) => -2,
+                (0, _, false) => 750 / divisor,
+                (_, _, true) => 1000 / divisor,
+                (_, _, false) => -1,
             }
         }
         17 | 18 => {
             // 17: Extended squitter
             // 18: Extended squitter/non-transponder
-            let addr = getbits(msg, 9, 32) as u32;
-
             let crc = modes_checksum(msg, msgbits);
-            match (crc, icao_filter_test(addr)) {
-                (0, true) => 1800,
+            let Some(info) = modes_checksum_diagnose(crc, msgbits, fix_errors) else {
+                return Some((msglen, -2, None));
+            };
+            let addr = correct_aa_field(getbits(msg, 9, 32) as u32, &info);
+            let divisor = info.errors as i32 + 1;
+            if info.errors != 0 {
+                corrected = Some(info);
+            }
+
+            match (info.errors, icao_filter.test(addr)) {
+                (_, true) => 1800 / divisor,
                 (0, false) => {
                     if df == 17 {
-                        icao_filter_add(addr);
+                        icao_filter.add(addr);
                     } else {
-                        icao_filter_add(addr | ICAO_FILTER_ADSB_NT);
+                        icao_filter.add(addr | ICAO_FILTER_ADSB_NT);
                     }
                     1400
                 }
-                (_, _) => -2,
+                (_, false) => 1400 / divisor,
             }
         }
         16 | 20 | 21 => {
@@ -112,7 +154,7 @@ pub fn score_modes_message(msg: &[u8]) -> Option<(MsgLen, i32)> {
             // 20: Comm-B, altitude reply
             // 21: Comm-B, identity reply
             let crc = modes_checksum(msg, MODES_LONG_MSG_BYTES * 8);
-            match icao_filter_test(crc) {
+            match icao_filter.test(crc) {
                 true => 1000,
                 false => -2,
             }
@@ -127,7 +169,7 @@ pub fn score_modes_message(msg: &[u8]) -> Option<(MsgLen, i32)> {
             // 30: Comm-D (ELM)
             // 31: Comm-D (ELM)
             let crc = modes_checksum(msg, MODES_LONG_MSG_BYTES * 8);
-            match icao_filter_test(crc) {
+            match icao_filter.test(crc) {
                 true => 1000,
                 false => -2,
             }
@@ -135,5 +177,108 @@ pub fn score_modes_message(msg: &[u8]) -> Option<(MsgLen, i32)> {
         _ => -2,
     };
 
-    Some((msglen, res))
+    Some((msglen, res, corrected))
+}
+
+// mode_s.c decodeID13Field
+/// Rearrange the 13 bit identity field into the hex Gillham (0xABCD) Mode A layout
+#[must_use]
+pub fn decode_id13_field(id13_field: u32) -> u32 {
+    let mut hex_gillham: u32 = 0;
+
+    if id13_field & 0x1000 != 0 {
+        hex_gillham |= 0x0010; // Bit 12 = C1
+    }
+    if id13_field & 0x0800 != 0 {
+        hex_gillham |= 0x1000; // Bit 11 = A1
+    }
+    if id13_field & 0x0400 != 0 {
+        hex_gillham |= 0x0020; // Bit 10 = C2
+    }
+    if id13_field & 0x0200 != 0 {
+        hex_gillham |= 0x2000; // Bit  9 = A2
+    }
+    if id13_field & 0x0100 != 0 {
+        hex_gillham |= 0x0040; // Bit  8 = C4
+    }
+    if id13_field & 0x0080 != 0 {
+        hex_gillham |= 0x4000; // Bit  7 = A4
+    }
+    // Bit  6 = X  or M
+    if id13_field & 0x0020 != 0 {
+        hex_gillham |= 0x0100; // Bit  5 = B1
+    }
+    if id13_field & 0x0010 != 0 {
+        hex_gillham |= 0x0001; // Bit  4 = D1 or Q
+    }
+    if id13_field & 0x0008 != 0 {
+        hex_gillham |= 0x0200; // Bit  3 = B2
+    }
+    if id13_field & 0x0004 != 0 {
+        hex_gillham |= 0x0002; // Bit  2 = D2
+    }
+    if id13_field & 0x0002 != 0 {
+        hex_gillham |= 0x0400; // Bit  1 = B4
+    }
+    if id13_field & 0x0001 != 0 {
+        hex_gillham |= 0x0004; // Bit  0 = D4
+    }
+
+    hex_gillham
+}
+
+// mode_s.c decodeAC13Field
+/// Decode the 13 bit altitude field of DF0, DF4, DF16 and DF20, in feet
+///
+/// Returns `None` for metric altitudes, which are not supported
+#[must_use]
+pub fn decode_ac13_field(ac13_field: u32) -> Option<i32> {
+    let m_bit = ac13_field & 0x0040; // set = meters, clear = feet
+    let q_bit = ac13_field & 0x0010; // set = 25 ft encoding, clear = Gillham Mode C encoding
+
+    if m_bit != 0 {
+        return None;
+    }
+
+    if q_bit != 0 {
+        // N is the 11 bit integer resulting from the removal of bit Q and M
+        let n = ((ac13_field & 0x1f80) >> 2) | ((ac13_field & 0x0020) >> 1) | (ac13_field & 0x000f);
+        // The final altitude is resulting number multiplied by 25, minus 1000.
+        Some(n as i32 * 25 - 1000)
+    } else {
+        // N is an 11 bit Gillham coded altitude
+        let n = mode_a_to_mode_c(decode_id13_field(ac13_field));
+        Some(if n < -12 { 0 } else { 100 * n })
+    }
+}
+
+// mode_s.c decodeAC12Field
+/// Decode the 12 bit altitude field of an airborne position extended squitter, in feet
+#[must_use]
+pub fn decode_ac12_field(ac12_field: u32) -> i32 {
+    let q_bit = ac12_field & 0x10; // Bit 48 = Q
+
+    if q_bit != 0 {
+        // N is the 11 bit integer resulting from the removal of bit Q at bit 4
+        let n = ((ac12_field & 0x0fe0) >> 1) | (ac12_field & 0x000f);
+        n as i32 * 25 - 1000
+    } else {
+        // Make N a 13 bit Gillham coded altitude by inserting M=0 at bit 6
+        let n = ((ac12_field & 0x0fc0) << 1) | (ac12_field & 0x003f);
+        let n = mode_a_to_mode_c(decode_id13_field(n));
+        if n < -12 { 0 } else { 100 * n }
+    }
+}
+
+const AIS_CHARSET: &[u8; 64] = b"?ABCDEFGHIJKLMNOPQRSTUVWXYZ????? ???????????????0123456789??????";
+
+// mode_s.c decodeModesMessage, identification
+/// Decode the 8 character callsign of an identification extended squitter
+#[must_use]
+pub fn decode_callsign(msg: &[u8]) -> String {
+    (0..8)
+        .map(|i| AIS_CHARSET[getbits(msg, 41 + i * 6, 46 + i * 6)] as char)
+        .collect::<String>()
+        .trim_end()
+        .to_string()
 }
@@ -0,0 +1,523 @@
+// This module includes functionality translated from net_io.c
+
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::cpr::Position;
+use crate::crc::modes_checksum_fix;
+use crate::demod_2400::{ModeSMessage, MsgLen};
+use crate::mode_ac::ModeACMessage;
+use crate::mode_s::{AltitudeSource, ExtendedSquitter, score_modes_message};
+use crate::{Demodulator, Error, MODES_LONG_MSG_BYTES, MODES_SHORT_MSG_BYTES};
+
+/// Escape byte that starts every Beast frame, doubled when it occurs in the payload
+pub const BEAST_ESCAPE: u8 = 0x1a;
+
+/// Beast message type for a Mode A/C reply
+pub const BEAST_MODE_AC: u8 = b'1';
+/// Beast message type for a 56 bit Mode S reply
+pub const BEAST_MODE_S_SHORT: u8 = b'2';
+/// Beast message type for a 112 bit Mode S reply
+pub const BEAST_MODE_S_LONG: u8 = b'3';
+
+// net_io.c modesSendBeastOutput
+/// Append `msg` to `out` in the Beast binary framing
+///
+/// `<0x1a> <type> <6 byte 12MHz timestamp> <1 byte signal> <message>`, with every `0x1a` after
+/// the leading one doubled.
+pub fn beast_encode(msg: &ModeSMessage, out: &mut Vec<u8>) {
+    let msg_type = match msg.msglen() {
+        MsgLen::Short => BEAST_MODE_S_SHORT,
+        MsgLen::Long => BEAST_MODE_S_LONG,
+    };
+
+    out.push(BEAST_ESCAPE);
+    out.push(msg_type);
+
+    for b in &msg.timestamp_12mhz().to_be_bytes()[2..] {
+        beast_push_escaped(out, *b);
+    }
+
+    beast_push_escaped(out, beast_signal(msg.signal_level()));
+
+    for b in msg.buffer() {
+        beast_push_escaped(out, *b);
+    }
+}
+
+/// Append a Mode A/C reply to `out` in the Beast binary framing, as [`beast_encode`] with type
+/// `'1'` and the two bytes of [`ModeACMessage::buffer`]
+pub fn beast_encode_mode_ac(msg: &ModeACMessage, out: &mut Vec<u8>) {
+    out.push(BEAST_ESCAPE);
+    out.push(BEAST_MODE_AC);
+
+    for b in &msg.timestamp_12mhz().to_be_bytes()[2..] {
+        beast_push_escaped(out, *b);
+    }
+
+    beast_push_escaped(out, beast_signal(msg.signal_level()));
+
+    for b in msg.buffer() {
+        beast_push_escaped(out, b);
+    }
+}
+
+/// Convert a signal level in the range [0..1] of full-scale power to the Beast signal byte
+#[inline(always)]
+fn beast_signal(signal_level: f64) -> u8 {
+    let sig = (signal_level.sqrt() * 255.0).round();
+    if signal_level > 0.0 && sig < 1.0 {
+        1
+    } else if sig > 255.0 {
+        255
+    } else {
+        sig as u8
+    }
+}
+
+#[inline(always)]
+fn beast_push_escaped(out: &mut Vec<u8>, b: u8) {
+    out.push(b);
+    if b == BEAST_ESCAPE {
+        out.push(BEAST_ESCAPE);
+    }
+}
+
+// net_io.c modesSendSBSOutput
+/// Append `msg` to `out` as a BaseStation `MSG,<type>,...` line
+///
+/// Nothing is written for downlink formats that have no SBS representation. Times are written
+/// in UTC. Decoding a position takes CPR state across several messages, so the latitude and
+/// longitude of a position message are `position`, such as the position [`Tracker::update`]
+/// decoded from `msg`, and are left empty when it is `None`.
+///
+/// [`Tracker::update`]: crate::track::Tracker::update
+pub fn sbs_encode(msg: &ModeSMessage, position: Option<Position>, out: &mut Vec<u8>) {
+    let decoded = msg.decode();
+
+    // Decide on the basic SBS Message Type
+    let msg_type = match (decoded.df, &decoded.me) {
+        (4 | 20, _) => 5,
+        (5 | 21, _) => 6,
+        (0 | 16, _) => 7,
+        (11, _) => 8,
+        (17 | 18, Some(ExtendedSquitter::Identification { .. })) => 1,
+        (17 | 18, Some(ExtendedSquitter::SurfacePosition { .. })) => 2,
+        (17 | 18, Some(ExtendedSquitter::AirbornePosition { .. })) => 3,
+        (17 | 18, Some(ExtendedSquitter::AirborneVelocity(_))) => 4,
+        _ => return,
+    };
+
+    let mut callsign = None;
+    let mut altitude = decoded.altitude;
+    let mut ground_speed = None;
+    let mut track = None;
+    let mut vertical_rate = None;
+
+    match &decoded.me {
+        Some(ExtendedSquitter::Identification { callsign: c, .. }) => callsign = Some(c.as_str()),
+        Some(ExtendedSquitter::SurfacePosition { ground_speed: gs, track: t, .. }) => {
+            ground_speed = *gs;
+            track = *t;
+        }
+        Some(ExtendedSquitter::AirbornePosition {
+            altitude: a,
+            altitude_source: AltitudeSource::Baro,
+            ..
+        }) => altitude = *a,
+        Some(ExtendedSquitter::AirborneVelocity(velocity)) => {
+            ground_speed = velocity.ground_speed;
+            track = velocity.track;
+            vertical_rate = velocity.vertical_rate;
+        }
+        _ => (),
+    }
+
+    let squawk = decoded.squawk();
+    let (date, time) = sbs_time(msg.sys_timestamp());
+    let (now_date, now_time) = sbs_time(SystemTime::now());
+
+    // Fields 1 to 10: SBS message type, ICAO address, reception and current time
+    let mut line = format!(
+        "MSG,{msg_type},111,11111,{:06X},111111,{date},{time},{now_date},{now_time}",
+        decoded.icao
+    );
+
+    // Field 11 is the callsign (if we have it)
+    line.push(',');
+    if let Some(callsign) = callsign {
+        line.push_str(callsign);
+    }
+
+    // Field 12 is the altitude (if we have it)
+    line.push(',');
+    if let Some(altitude) = altitude {
+        let _ = write!(line, "{altitude}");
+    }
+
+    // Field 13 is the ground Speed (if we have it)
+    line.push(',');
+    if let Some(ground_speed) = ground_speed {
+        let _ = write!(line, "{}", ground_speed.round());
+    }
+
+    // Field 14 is the ground Heading (if we have it)
+    line.push(',');
+    if let Some(track) = track {
+        let _ = write!(line, "{}", track.round());
+    }
+
+    // Fields 15 and 16 are the Lat/Lon (if we have it)
+    match position {
+        Some(position) if msg_type == 2 || msg_type == 3 => {
+            let _ = write!(line, ",{:.5},{:.5}", position.lat, position.lon);
+        }
+        _ => line.push_str(",,"),
+    }
+
+    // Field 17 is the VerticalRate (if we have it)
+    line.push(',');
+    if let Some(vertical_rate) = vertical_rate {
+        let _ = write!(line, "{vertical_rate}");
+    }
+
+    // Field 18 is the Squawk (if we have it)
+    line.push(',');
+    if let Some(squawk) = squawk {
+        let _ = write!(line, "{squawk}");
+    }
+
+    // Field 19 is the Squawk Changing Alert flag (if we have it)
+    line.push_str(sbs_flag(decoded.flight_status.map(|fs| fs.alert())));
+
+    // Field 20 is the Squawk Emergency flag (if we have it)
+    line.push_str(sbs_flag(squawk.map(|s| s.is_emergency())));
+
+    // Field 21 is the Squawk Ident flag (if we have it)
+    line.push_str(sbs_flag(decoded.flight_status.map(|fs| fs.spi())));
+
+    // Field 22 is the OnTheGround flag (if we have it)
+    line.push_str(sbs_flag(decoded.on_ground()));
+
+    line.push_str("\r\n");
+    out.extend_from_slice(line.as_bytes());
+}
+
+#[inline(always)]
+fn sbs_flag(flag: Option<bool>) -> &'static str {
+    match flag {
+        Some(true) => ",-1",
+        Some(false) => ",0",
+        None => ",",
+    }
+}
+
+/// Format `time` as the SBS `YYYY/MM/DD` and `HH:MM:SS.mmm` fields, in UTC
+fn sbs_time(time: SystemTime) -> (String, String) {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let days = (secs / 86_400) as i64;
+    let secs_of_day = secs % 86_400;
+
+    // days to civil date, from http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (
+        format!("{year:04}/{month:02}/{day:02}"),
+        format!(
+            "{:02}:{:02}:{:02}.{:03}",
+            secs_of_day / 3600,
+            (secs_of_day / 60) % 60,
+            secs_of_day % 60,
+            since_epoch.subsec_millis()
+        ),
+    )
+}
+
+/// Longest AVR line, `<` with a timestamp, signal and long message, without the line ending,
+/// before the rest of a line is discarded
+const AVR_MAX_LINE: usize = 1 + 12 + 2 + MODES_LONG_MSG_BYTES * 2 + 1;
+
+/// Reply read from Beast or AVR input, to be checked with [`Demodulator::decode_frame`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// 2 bytes of a Mode A/C reply as [`ModeACMessage::buffer`], or 7 or 14 bytes of a Mode S
+    /// reply
+    pub data: Vec<u8>,
+    /// 12MHz clock tick of the reply at the receiver that sent it, 0 when not given
+    pub timestamp_12mhz: u64,
+    /// Signal level in the range [0..1] of full-scale power, 0 when not given
+    pub signal_level: f64,
+}
+
+/// Reply from network input, checked as the replies from the sdr
+#[derive(Debug)]
+pub enum Reply {
+    ModeS(ModeSMessage),
+    ModeAC(ModeACMessage),
+}
+
+impl Demodulator {
+    /// Check a frame from network input as the demodulated messages are checked, with the CRC,
+    /// the ICAO filter and the error correction of [`crate::DemodulatorConfig::fix_errors`]
+    ///
+    /// Returns `None` for a Mode S frame that fails the checks. Replies from the network have no
+    /// phase or sample offset.
+    pub fn decode_frame(&mut self, frame: &Frame, sys_timestamp: SystemTime) -> Option<Reply> {
+        self.expire_icao_filter(sys_timestamp);
+        if let Ok(buffer) = <[u8; 2]>::try_from(frame.data.as_slice()) {
+            return Some(Reply::ModeAC(ModeACMessage::from_buffer(
+                buffer,
+                frame.signal_level,
+                frame.timestamp_12mhz,
+                sys_timestamp,
+            )));
+        }
+        if frame.data.len() != MODES_SHORT_MSG_BYTES && frame.data.len() != MODES_LONG_MSG_BYTES {
+            return None;
+        }
+
+        let (msglen, score, corrected) =
+            score_modes_message(&frame.data, &mut self.icao_filter, self.config.fix_errors)?;
+        if score < 0 {
+            return None;
+        }
+        let mut msg = [0_u8; MODES_LONG_MSG_BYTES];
+        msg[..frame.data.len()].copy_from_slice(&frame.data);
+        if let Some(info) = &corrected {
+            modes_checksum_fix(&mut msg, info);
+        }
+        Some(Reply::ModeS(ModeSMessage {
+            msglen,
+            msg,
+            signal_level: frame.signal_level,
+            score,
+            phase: None,
+            sample_offset: 0,
+            timestamp_12mhz: frame.timestamp_12mhz,
+            sys_timestamp,
+            corrected,
+        }))
+    }
+}
+
+/// Streaming parser of the Beast binary framing written by [`beast_encode`]
+///
+/// Frames of types `'1'`, `'2'` and `'3'` are read, a partial frame at the end of the input is
+/// kept for the next call.
+#[derive(Debug, Default)]
+pub struct BeastDecoder {
+    buf: Vec<u8>,
+    /// skipping to the next frame after an error, which isn't reported again until then
+    resync: bool,
+}
+
+/// Result of parsing from an escape byte
+enum BeastParse {
+    Frame(Frame, usize),
+    Incomplete,
+    /// Bytes to skip, and why
+    Invalid(usize, String),
+}
+
+impl BeastDecoder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse the frames completed by `data`
+    ///
+    /// Bytes that aren't part of a frame, such as a frame of another type or one cut short by
+    /// the next escape, are skipped up to the next frame as one [`Error::MalformedInput`].
+    pub fn decode(&mut self, data: &[u8]) -> Vec<Result<Frame, Error>> {
+        self.buf.extend_from_slice(data);
+
+        let mut frames = vec![];
+        let mut pos = 0;
+        while pos < self.buf.len() {
+            let start = self.buf[pos..].iter().position(|&b| b == BEAST_ESCAPE);
+            let skip = start.unwrap_or(self.buf.len() - pos);
+            if skip > 0 {
+                if !self.resync {
+                    frames.push(Err(Error::MalformedInput("bytes outside a frame".into())));
+                    self.resync = true;
+                }
+                pos += skip;
+                continue;
+            }
+
+            match beast_parse(&self.buf[pos..]) {
+                BeastParse::Frame(frame, len) => {
+                    frames.push(Ok(frame));
+                    self.resync = false;
+                    pos += len;
+                }
+                BeastParse::Incomplete => break,
+                BeastParse::Invalid(len, reason) => {
+                    if !self.resync {
+                        frames.push(Err(Error::MalformedInput(reason)));
+                        self.resync = true;
+                    }
+                    pos += len;
+                }
+            }
+        }
+        self.buf.drain(..pos);
+
+        frames
+    }
+}
+
+/// Parse the frame starting at the escape byte `buf[0]`
+fn beast_parse(buf: &[u8]) -> BeastParse {
+    let Some(&msg_type) = buf.get(1) else {
+        return BeastParse::Incomplete;
+    };
+    let msg_len = match msg_type {
+        BEAST_MODE_AC => 2,
+        BEAST_MODE_S_SHORT => MODES_SHORT_MSG_BYTES,
+        BEAST_MODE_S_LONG => MODES_LONG_MSG_BYTES,
+        _ => {
+            // skip to the next escape, a doubled one can't start a frame
+            let skip =
+                buf[2..].iter().position(|&b| b == BEAST_ESCAPE).map_or(buf.len(), |p| p + 2);
+            return BeastParse::Invalid(skip, format!("beast frame of type {msg_type:#04x}"));
+        }
+    };
+
+    // timestamp, signal and message, unescaped
+    let mut payload = Vec::with_capacity(6 + 1 + msg_len);
+    let mut i = 2;
+    while payload.len() < payload.capacity() {
+        match (buf.get(i), buf.get(i + 1)) {
+            (None, _) | (Some(&BEAST_ESCAPE), None) => return BeastParse::Incomplete,
+            (Some(&BEAST_ESCAPE), Some(&BEAST_ESCAPE)) => {
+                payload.push(BEAST_ESCAPE);
+                i += 2;
+            }
+            (Some(&BEAST_ESCAPE), Some(_)) => {
+                return BeastParse::Invalid(i, "beast frame cut short by the next frame".into());
+            }
+            (Some(&b), _) => {
+                payload.push(b);
+                i += 1;
+            }
+        }
+    }
+
+    let mut timestamp = [0_u8; 8];
+    timestamp[2..].copy_from_slice(&payload[..6]);
+    let frame = Frame {
+        data: payload[7..].to_vec(),
+        timestamp_12mhz: u64::from_be_bytes(timestamp),
+        signal_level: beast_signal_level(payload[6]),
+    };
+    BeastParse::Frame(frame, i)
+}
+
+/// Convert the Beast signal byte back to a signal level, the inverse of [`beast_signal`]
+#[inline(always)]
+fn beast_signal_level(sig: u8) -> f64 {
+    let sig = f64::from(sig) / 255.0;
+    sig * sig
+}
+
+/// Streaming parser of AVR lines
+///
+/// Reads `*<message>;`, `@<timestamp><message>;` and `<<timestamp><signal><message>;`, with the
+/// 12MHz timestamp as 12 hex digits and the Beast signal byte as 2. A partial line at the end of
+/// the input is kept for the next call.
+#[derive(Debug, Default)]
+pub struct AvrDecoder {
+    buf: Vec<u8>,
+    /// the rest of the current line is discarded, as it is too long
+    skip_line: bool,
+}
+
+impl AvrDecoder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse the lines completed by `data`
+    ///
+    /// Lines that aren't an AVR frame are an [`Error::MalformedInput`], empty lines are skipped.
+    pub fn decode(&mut self, data: &[u8]) -> Vec<Result<Frame, Error>> {
+        self.buf.extend_from_slice(data);
+
+        let mut frames = vec![];
+        let mut pos = 0;
+        while let Some(end) = self.buf[pos..].iter().position(|&b| b == b'\n') {
+            let line = self.buf[pos..pos + end].trim_ascii();
+            if self.skip_line {
+                self.skip_line = false;
+            } else if !line.is_empty() {
+                frames.push(avr_parse(line));
+            }
+            pos += end + 1;
+        }
+        self.buf.drain(..pos);
+
+        // a CRLF line ending may be split between reads
+        let unfinished = self.buf.strip_suffix(b"\r").unwrap_or(&self.buf);
+        if unfinished.len() > AVR_MAX_LINE {
+            if !self.skip_line {
+                frames.push(Err(Error::MalformedInput(format!(
+                    "avr line longer than {AVR_MAX_LINE} bytes"
+                ))));
+            }
+            self.buf.clear();
+            self.skip_line = true;
+        }
+
+        frames
+    }
+}
+
+/// Parse one AVR line, without the line ending
+fn avr_parse(line: &[u8]) -> Result<Frame, Error> {
+    let malformed = || Error::MalformedInput(format!("avr line {}", line.escape_ascii()));
+
+    let (&first, rest) = line.split_first().ok_or_else(malformed)?;
+    let hex = rest.strip_suffix(b";").ok_or_else(malformed)?;
+    let header_len = match first {
+        b'*' => 0,
+        b'@' => 12,
+        b'<' => 14,
+        _ => return Err(malformed()),
+    };
+    if hex.len() < header_len || !hex.len().is_multiple_of(2) {
+        return Err(malformed());
+    }
+    let bytes = hex
+        .chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).map_err(|_| malformed())?;
+            u8::from_str_radix(pair, 16).map_err(|_| malformed())
+        })
+        .collect::<Result<Vec<u8>, Error>>()?;
+    let (header, data) = bytes.split_at(header_len / 2);
+    if ![2, MODES_SHORT_MSG_BYTES, MODES_LONG_MSG_BYTES].contains(&data.len()) {
+        return Err(malformed());
+    }
+
+    let mut timestamp = [0_u8; 8];
+    let mut signal_level = 0.0;
+    if let Some(ts) = header.get(..6) {
+        timestamp[2..].copy_from_slice(ts);
+    }
+    if let Some(&sig) = header.get(6) {
+        signal_level = beast_signal_level(sig);
+    }
+    Ok(Frame { data: data.to_vec(), timestamp_12mhz: u64::from_be_bytes(timestamp), signal_level })
+}
@@ -0,0 +1,375 @@
+// This module includes functionality translated from track.c
+
+use std::collections::HashMap;
+use std::collections::hash_map;
+use std::time::{Duration, SystemTime};
+
+use crate::cpr::{self, CprError, Position, Receiver};
+use crate::demod_2400::ModeSMessage;
+use crate::mode_s::{
+    AirspeedType, AltitudeSource, CprFrame, DecodedMessage, ExtendedSquitter, Squawk,
+};
+
+/// Time since the last message before an aircraft is removed
+pub const TRACK_AIRCRAFT_TTL: Duration = Duration::from_secs(300);
+/// Time since the last position before the position of an aircraft is removed
+pub const TRACK_AIRCRAFT_POSITION_TTL: Duration = Duration::from_secs(60);
+
+/// Maximum time between the even and odd frames used for global CPR decoding
+const TRACK_CPR_AIRBORNE_MAX_ELAPSED: Duration = Duration::from_secs(10);
+const TRACK_CPR_SURFACE_MAX_ELAPSED: Duration = Duration::from_secs(50);
+
+/// Number of signal levels kept for the RSSI of an aircraft
+const TRACK_SIGNAL_LEVELS: usize = 8;
+
+/// Configuration of a [`Tracker`]
+#[derive(Debug, Clone)]
+pub struct TrackerConfig {
+    /// Time since the last message before an aircraft is removed
+    pub aircraft_ttl: Duration,
+    /// Time since the last position before the position of an aircraft is removed
+    pub position_ttl: Duration,
+    /// Receiver location and range, used for surface and local CPR decoding
+    pub receiver: Receiver,
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        Self {
+            aircraft_ttl: TRACK_AIRCRAFT_TTL,
+            position_ttl: TRACK_AIRCRAFT_POSITION_TTL,
+            receiver: Receiver::default(),
+        }
+    }
+}
+
+/// Last CPR frame of one parity
+#[derive(Debug, Clone, Copy)]
+struct CprState {
+    frame: CprFrame,
+    surface: bool,
+    time: SystemTime,
+}
+
+/// State of one aircraft, aggregated from all its messages
+#[derive(Debug, Clone)]
+pub struct Aircraft {
+    /// ICAO address
+    pub icao: u32,
+    pub callsign: Option<String>,
+    /// Emitter category, set A to D in the high nibble, `0xA3` for A3
+    pub category: Option<u8>,
+    pub squawk: Option<Squawk>,
+    /// Barometric altitude in feet
+    pub altitude: Option<i32>,
+    /// GNSS altitude in feet
+    pub altitude_gnss: Option<i32>,
+    /// Ground speed in knots
+    pub ground_speed: Option<f64>,
+    /// Track over ground in degrees
+    pub track: Option<f64>,
+    /// Heading in degrees
+    pub heading: Option<f64>,
+    /// Airspeed in knots
+    pub airspeed: Option<(u32, AirspeedType)>,
+    /// Vertical rate in feet per minute
+    pub vertical_rate: Option<i32>,
+    pub on_ground: Option<bool>,
+    pub position: Option<Position>,
+    /// Time of the last decoded position
+    pub position_time: Option<SystemTime>,
+    /// ADS-B version number
+    pub adsb_version: Option<u8>,
+    /// Emergency state from the last emergency status extended squitter
+    pub emergency: Option<u8>,
+    /// Count of messages received
+    pub messages: u64,
+    pub first_seen: SystemTime,
+    pub last_seen: SystemTime,
+    signal_levels: [f64; TRACK_SIGNAL_LEVELS],
+    signal_next: usize,
+    even_cpr: Option<CprState>,
+    odd_cpr: Option<CprState>,
+}
+
+impl Aircraft {
+    fn new(icao: u32, now: SystemTime) -> Self {
+        Self {
+            icao,
+            callsign: None,
+            category: None,
+            squawk: None,
+            altitude: None,
+            altitude_gnss: None,
+            ground_speed: None,
+            track: None,
+            heading: None,
+            airspeed: None,
+            vertical_rate: None,
+            on_ground: None,
+            position: None,
+            position_time: None,
+            adsb_version: None,
+            emergency: None,
+            messages: 0,
+            first_seen: now,
+            last_seen: now,
+            signal_levels: [0.0; TRACK_SIGNAL_LEVELS],
+            signal_next: 0,
+            even_cpr: None,
+            odd_cpr: None,
+        }
+    }
+
+    /// Signal levels of the last messages, in the range [0..1] as a fraction of full-scale power
+    #[must_use]
+    pub fn signal_levels(&self) -> &[f64] {
+        let len = (self.messages as usize).min(TRACK_SIGNAL_LEVELS);
+        &self.signal_levels[..len]
+    }
+
+    /// Average signal level of the last messages in dBFS
+    #[must_use]
+    pub fn rssi(&self) -> f64 {
+        let levels = self.signal_levels();
+        if levels.is_empty() {
+            return f64::NEG_INFINITY;
+        }
+        let average = levels.iter().sum::<f64>() / levels.len() as f64;
+        10.0 * (average + 1e-5).log10()
+    }
+
+    /// Time between the last message and `now`
+    #[must_use]
+    pub fn seen(&self, now: SystemTime) -> Duration {
+        now.duration_since(self.last_seen).unwrap_or_default()
+    }
+
+    fn update_signal(&mut self, signal_level: f64) {
+        self.signal_levels[self.signal_next] = signal_level;
+        self.signal_next = (self.signal_next + 1) % TRACK_SIGNAL_LEVELS;
+    }
+
+    // track.c updatePosition
+    fn update_position(
+        &mut self,
+        config: &TrackerConfig,
+        frame: CprFrame,
+        surface: bool,
+        now: SystemTime,
+    ) {
+        let state = CprState { frame, surface, time: now };
+        if frame.odd {
+            self.odd_cpr = Some(state);
+        } else {
+            self.even_cpr = Some(state);
+        }
+
+        let global = self.decode_global(config, &state);
+        let result = match global {
+            Ok(position) => Ok(position),
+            // the pair crossed a latitude zone, wait for the next pair
+            Err(CprError::ZoneMismatch | CprError::OutOfRange) => return,
+            Err(_) => {
+                // local decoding, relative to the last position if recent enough, otherwise
+                // relative to the receiver
+                let reference = match (self.position, self.position_time) {
+                    (Some(position), Some(time)) if elapsed(time, now) <= config.position_ttl => {
+                        Some(position)
+                    }
+                    _ => None,
+                };
+                config.receiver.decode_local(reference.as_ref(), &frame, surface)
+            }
+        };
+
+        if let Ok(position) = result {
+            self.position = Some(position);
+            self.position_time = Some(now);
+        }
+    }
+
+    fn decode_global(
+        &self,
+        config: &TrackerConfig,
+        state: &CprState,
+    ) -> Result<Position, CprError> {
+        let (Some(even), Some(odd)) = (self.even_cpr, self.odd_cpr) else {
+            return Err(CprError::BadData);
+        };
+        let max_elapsed = if state.surface {
+            TRACK_CPR_SURFACE_MAX_ELAPSED
+        } else {
+            TRACK_CPR_AIRBORNE_MAX_ELAPSED
+        };
+        if even.surface != odd.surface || elapsed(even.time, odd.time) > max_elapsed {
+            return Err(CprError::BadData);
+        }
+
+        // without a receiver location, surface positions are picked relative to the last
+        // position of the aircraft
+        if state.surface && config.receiver.location.is_none() {
+            let reference = self.position.ok_or(CprError::NoReference)?;
+            let position =
+                cpr::decode_cpr_surface(&reference, &even.frame, &odd.frame, state.frame.odd)?;
+            return config.receiver.check_range(position);
+        }
+
+        config.receiver.decode_global(&even.frame, &odd.frame, state.frame.odd, state.surface)
+    }
+}
+
+/// Absolute time between `a` and `b`
+#[inline(always)]
+fn elapsed(a: SystemTime, b: SystemTime) -> Duration {
+    match a.duration_since(b) {
+        Ok(d) => d,
+        Err(e) => e.duration(),
+    }
+}
+
+/// Aircraft state table, built from demodulated messages
+#[derive(Debug, Default)]
+pub struct Tracker {
+    aircraft: HashMap<u32, Aircraft>,
+    config: TrackerConfig,
+}
+
+impl Tracker {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_config(config: TrackerConfig) -> Self {
+        Self { aircraft: HashMap::new(), config }
+    }
+
+    #[must_use]
+    pub fn config(&self) -> &TrackerConfig {
+        &self.config
+    }
+
+    /// Update the state of an aircraft from a demodulated message
+    pub fn update(&mut self, msg: &ModeSMessage) -> &Aircraft {
+        self.update_decoded(&msg.decode(), msg.signal_level(), msg.sys_timestamp())
+    }
+
+    // track.c trackUpdateFromMessage
+    /// Update the state of an aircraft from a decoded message, received at `now` with
+    /// `signal_level` as a fraction of full-scale power
+    pub fn update_decoded(
+        &mut self,
+        decoded: &DecodedMessage,
+        signal_level: f64,
+        now: SystemTime,
+    ) -> &Aircraft {
+        let config = &self.config;
+        let a =
+            self.aircraft.entry(decoded.icao).or_insert_with(|| Aircraft::new(decoded.icao, now));
+
+        a.update_signal(signal_level);
+        a.messages += 1;
+        if now > a.last_seen {
+            a.last_seen = now;
+        }
+
+        if let Some(altitude) = decoded.altitude {
+            a.altitude = Some(altitude);
+        }
+        if let Some(squawk) = decoded.squawk() {
+            a.squawk = Some(squawk);
+        }
+        if let Some(on_ground) = decoded.on_ground() {
+            a.on_ground = Some(on_ground);
+        }
+
+        match &decoded.me {
+            Some(ExtendedSquitter::Identification { category, callsign }) => {
+                a.category = Some(*category);
+                a.callsign = Some(callsign.clone());
+            }
+            Some(ExtendedSquitter::SurfacePosition { ground_speed, track, cpr }) => {
+                if ground_speed.is_some() {
+                    a.ground_speed = *ground_speed;
+                }
+                if track.is_some() {
+                    a.track = *track;
+                }
+                a.update_position(config, *cpr, true, now);
+            }
+            Some(ExtendedSquitter::AirbornePosition { altitude, altitude_source, cpr, .. }) => {
+                if altitude.is_some() {
+                    match altitude_source {
+                        AltitudeSource::Baro => a.altitude = *altitude,
+                        AltitudeSource::Gnss => a.altitude_gnss = *altitude,
+                    }
+                }
+                a.update_position(config, *cpr, false, now);
+            }
+            Some(ExtendedSquitter::AirborneVelocity(velocity)) => {
+                if velocity.ground_speed.is_some() {
+                    a.ground_speed = velocity.ground_speed;
+                }
+                if velocity.track.is_some() {
+                    a.track = velocity.track;
+                }
+                if velocity.heading.is_some() {
+                    a.heading = velocity.heading;
+                }
+                if velocity.airspeed.is_some() {
+                    a.airspeed = velocity.airspeed;
+                }
+                if velocity.vertical_rate.is_some() {
+                    a.vertical_rate = velocity.vertical_rate;
+                }
+            }
+            Some(ExtendedSquitter::EmergencyStatus { emergency, .. }) => {
+                a.emergency = Some(*emergency);
+            }
+            Some(ExtendedSquitter::OperationalStatus { version }) => {
+                a.adsb_version = Some(*version);
+            }
+            _ => (),
+        }
+
+        a
+    }
+
+    // track.c trackRemoveStaleAircraft
+    /// Remove aircraft not seen within the aircraft ttl of `now`, and positions older than the
+    /// position ttl
+    pub fn expire(&mut self, now: SystemTime) {
+        let config = &self.config;
+        self.aircraft.retain(|_, a| a.seen(now) <= config.aircraft_ttl);
+        for a in self.aircraft.values_mut() {
+            if let Some(time) = a.position_time
+                && now.duration_since(time).unwrap_or_default() > config.position_ttl
+            {
+                a.position = None;
+                a.position_time = None;
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn get(&self, icao: u32) -> Option<&Aircraft> {
+        self.aircraft.get(&icao)
+    }
+
+    /// Iterate over all tracked aircraft, in no particular order
+    pub fn iter(&self) -> hash_map::Values<'_, u32, Aircraft> {
+        self.aircraft.values()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.aircraft.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.aircraft.is_empty()
+    }
+}
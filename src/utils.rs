@@ -1,47 +1,155 @@
 // crate
 // third-party
-use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use byteorder::{LittleEndian, WriteBytesExt};
 use num_complex::Complex;
 
-use crate::MagnitudeBuffer;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::SystemTime;
 
-pub fn save_test_data(data: &[Complex<i16>]) {
+use crate::convert::{DcFilter, SampleFormat, push_mag_bytes, push_mag_iq};
+use crate::{Error, MODES_MAG_BUF_SAMPLES, MagnitudeBuffer, SampleRate, TRAILING_SAMPLES};
+
+/// Write `data` to `test_<unix millis>.iq` in the current directory, as read by [`IqReader`]
+pub fn save_test_data(data: &[Complex<i16>]) -> Result<(), Error> {
     let now = std::time::SystemTime::now()
         .duration_since(std::time::SystemTime::UNIX_EPOCH)
-        .unwrap()
+        .unwrap_or_default()
         .as_millis();
     let name = format!("test_{now}.iq");
-    let mut file = std::fs::File::create(name).unwrap();
+    let mut file = BufWriter::new(File::create(name)?);
 
     for d in data {
-        file.write_i16::<LittleEndian>(d.im).unwrap();
-        file.write_i16::<LittleEndian>(d.re).unwrap();
+        file.write_i16::<LittleEndian>(d.im)?;
+        file.write_i16::<LittleEndian>(d.re)?;
     }
+    file.flush()?;
+    Ok(())
+}
+
+/// Streaming reader of raw IQ samples, in chunks of whole samples
+///
+/// Each chunk fits in one [`MagnitudeBuffer`], and can be given to
+/// [`crate::Demodulator::demodulate_bytes`] or [`MagnitudeConverter::to_mag_bytes`].
+#[derive(Debug)]
+pub struct IqReader<R> {
+    reader: R,
+    format: SampleFormat,
+    chunk_samples: usize,
+    samples_read: u64,
+    buf: Vec<u8>,
 }
 
-#[must_use]
-pub fn read_test_data(filepath: &str) -> [Complex<i16>; 0x20000] {
-    let mut file = std::fs::File::open(filepath).unwrap();
-    let mut r_buf = [Complex::new(0, 0); 0x20000];
+impl IqReader<BufReader<File>> {
+    /// Open a recording of samples in `format`
+    pub fn open(path: impl AsRef<Path>, format: SampleFormat) -> Result<Self, Error> {
+        Ok(Self::new(BufReader::new(File::open(path)?), format))
+    }
+}
+
+impl<R: Read> IqReader<R> {
+    /// Read samples in `format` from `reader`, in chunks of `MODES_MAG_BUF_SAMPLES`
+    pub fn new(reader: R, format: SampleFormat) -> Self {
+        Self { reader, format, chunk_samples: MODES_MAG_BUF_SAMPLES, samples_read: 0, buf: vec![] }
+    }
+
+    /// Set the number of samples per chunk, at most `MODES_MAG_BUF_SAMPLES`
+    #[must_use]
+    pub fn with_chunk_samples(mut self, chunk_samples: usize) -> Self {
+        self.chunk_samples = chunk_samples.clamp(1, MODES_MAG_BUF_SAMPLES);
+        self
+    }
+
+    #[must_use]
+    pub fn format(&self) -> SampleFormat {
+        self.format
+    }
+
+    /// Count of samples read so far
+    #[must_use]
+    pub fn samples_read(&self) -> u64 {
+        self.samples_read
+    }
+
+    /// Read the next chunk of raw samples, `None` at the end of the input
+    ///
+    /// The last chunk may be shorter. An input ending in the middle of a sample is an
+    /// [`Error::MalformedInput`].
+    pub fn read_chunk(&mut self) -> Result<Option<&[u8]>, Error> {
+        let bytes_per_sample = self.format.bytes_per_sample();
+        self.buf.resize(self.chunk_samples * bytes_per_sample, 0);
 
-    let mut i = 0;
-    loop {
-        let im = file.read_i16::<LittleEndian>().unwrap();
-        let re = file.read_i16::<LittleEndian>().unwrap();
-        r_buf[i] = Complex::new(re, im);
+        let mut filled = 0;
+        while filled < self.buf.len() {
+            match self.reader.read(&mut self.buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
 
-        i += 1;
-        if i == 0x20000 {
-            break;
+        if filled % bytes_per_sample != 0 {
+            return Err(Error::MalformedInput(format!(
+                "input ends within a {} sample",
+                self.format
+            )));
+        }
+        if filled == 0 {
+            return Ok(None);
         }
+
+        self.samples_read += (filled / bytes_per_sample) as u64;
+        Ok(Some(&self.buf[..filled]))
+    }
+}
+
+impl<R: Read> Iterator for IqReader<R> {
+    type Item = Result<Vec<u8>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_chunk().map(|chunk| chunk.map(<[u8]>::to_vec)).transpose()
     }
+}
 
-    r_buf
+/// Magnitudes of `data`, at most `MODES_MAG_BUF_SAMPLES` samples
+pub fn to_mag(data: &[Complex<i16>]) -> Result<MagnitudeBuffer, Error> {
+    check_len(data.len())?;
+    let mut outbuf = MagnitudeBuffer::default();
+    push_mag(data, &mut outbuf);
+    Ok(outbuf)
 }
 
-#[must_use]
-pub fn to_mag(data: &[Complex<i16>]) -> MagnitudeBuffer {
+/// [`to_mag`] for raw samples in `format`
+pub fn to_mag_bytes(format: SampleFormat, data: &[u8]) -> Result<MagnitudeBuffer, Error> {
+    check_bytes(format, data)?;
     let mut outbuf = MagnitudeBuffer::default();
+    push_mag_bytes(format, data, &mut DcFilter::default(), &mut outbuf);
+    Ok(outbuf)
+}
+
+/// Check that `len` samples fit in one [`MagnitudeBuffer`]
+fn check_len(len: usize) -> Result<(), Error> {
+    if len > MODES_MAG_BUF_SAMPLES {
+        return Err(Error::BufferOverflow { len, capacity: MODES_MAG_BUF_SAMPLES });
+    }
+    Ok(())
+}
+
+/// [`check_len`] for raw samples in `format`, which must hold whole samples
+fn check_bytes(format: SampleFormat, data: &[u8]) -> Result<(), Error> {
+    if !data.len().is_multiple_of(format.bytes_per_sample()) {
+        return Err(Error::MalformedInput(format!(
+            "{} bytes is not a whole number of {format} samples",
+            data.len()
+        )));
+    }
+    check_len(data.len() / format.bytes_per_sample())
+}
+
+#[inline(always)]
+fn push_mag(data: &[Complex<i16>], outbuf: &mut MagnitudeBuffer) {
     for b in data {
         // TODO: lookup table
         let i = b.im;
@@ -50,9 +158,102 @@ pub fn to_mag(data: &[Complex<i16>]) -> MagnitudeBuffer {
         let fi = f32::from(i) / (1 << 15) as f32;
         let fq = f32::from(q) / (1 << 15) as f32;
 
-        let mag_sqr = fi.mul_add(fi, fq * fq);
-        let mag = f32::sqrt(mag_sqr);
-        outbuf.push(mag.mul_add(f32::from(u16::MAX), 0.5) as u16);
+        push_mag_iq(fi, fq, outbuf);
+    }
+}
+
+/// Stateful [`to_mag`] for a continuous stream of samples
+///
+/// The last `TRAILING_SAMPLES` of each buffer are carried over to the start of the next, as
+/// the `fifo` of dump1090 does, so that messages straddling two reads are still demodulated.
+/// The running sample count is used for the buffer timestamps.
+#[derive(Debug)]
+pub struct MagnitudeConverter {
+    trailing: [u16; TRAILING_SAMPLES],
+    sample_counter: u64,
+    format: SampleFormat,
+    sample_rate: SampleRate,
+    dc_filter: DcFilter,
+}
+
+impl Default for MagnitudeConverter {
+    fn default() -> Self {
+        Self::new(SampleFormat::default())
+    }
+}
+
+impl MagnitudeConverter {
+    /// Converter for raw samples in `format`, see [`Self::to_mag_bytes`]
+    #[must_use]
+    pub fn new(format: SampleFormat) -> Self {
+        Self {
+            trailing: [0_u16; TRAILING_SAMPLES],
+            sample_counter: 0,
+            format,
+            sample_rate: SampleRate::default(),
+            dc_filter: DcFilter::default(),
+        }
+    }
+
+    /// Set the sample rate of the converted samples, 2.4MHz by default
+    #[must_use]
+    pub fn with_sample_rate(mut self, sample_rate: SampleRate) -> Self {
+        self.sample_rate = sample_rate;
+        self.dc_filter = DcFilter::new(sample_rate);
+        self
+    }
+
+    #[must_use]
+    pub fn format(&self) -> SampleFormat {
+        self.format
+    }
+
+    #[must_use]
+    pub fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    /// Convert the next block of samples, the first of which was received at `sys_timestamp`
+    ///
+    /// On error the samples are dropped, and the stream continues as if they were never given.
+    pub fn to_mag(
+        &mut self,
+        data: &[Complex<i16>],
+        sys_timestamp: SystemTime,
+    ) -> Result<MagnitudeBuffer, Error> {
+        check_len(data.len())?;
+        let mut outbuf = self.start_buffer();
+        push_mag(data, &mut outbuf);
+        self.finish_buffer(&mut outbuf, sys_timestamp);
+        Ok(outbuf)
+    }
+
+    /// Convert the next block of raw samples in the format of this converter, the first of
+    /// which was received at `sys_timestamp`
+    pub fn to_mag_bytes(
+        &mut self,
+        data: &[u8],
+        sys_timestamp: SystemTime,
+    ) -> Result<MagnitudeBuffer, Error> {
+        check_bytes(self.format, data)?;
+        let mut outbuf = self.start_buffer();
+        push_mag_bytes(self.format, data, &mut self.dc_filter, &mut outbuf);
+        self.finish_buffer(&mut outbuf, sys_timestamp);
+        Ok(outbuf)
+    }
+
+    fn start_buffer(&self) -> MagnitudeBuffer {
+        let mut outbuf =
+            MagnitudeBuffer { sample_rate: self.sample_rate, ..MagnitudeBuffer::default() };
+        outbuf.data[..TRAILING_SAMPLES].copy_from_slice(&self.trailing);
+        outbuf
+    }
+
+    fn finish_buffer(&mut self, outbuf: &mut MagnitudeBuffer, sys_timestamp: SystemTime) {
+        outbuf.set_timestamps(self.sample_counter, sys_timestamp);
+
+        self.sample_counter += outbuf.length as u64;
+        self.trailing
+            .copy_from_slice(&outbuf.data[outbuf.length..outbuf.length + TRAILING_SAMPLES]);
     }
-    outbuf
 }